@@ -0,0 +1,427 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use indexmap::IndexMap;
+
+use crate::parse::{Choice, SectionIdentifier, Story};
+
+/// problems with the shape of a story, that still parses fine: sections that
+/// can't be got to, or that can't be got out of
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Analysis {
+    unreachable: Vec<SectionIdentifier>,
+    dead_ends: Vec<SectionIdentifier>,
+    loops: Vec<Vec<SectionIdentifier>>,
+    exhaustible: Vec<SectionIdentifier>,
+    single_path: Vec<SectionIdentifier>,
+}
+
+impl Story {
+    pub fn analyze(&self) -> Analysis {
+        let graph = Graph::new(self);
+
+        let reachable = graph.reachable(self.metadata().start());
+        let can_end = graph.can_reach_ending(self);
+
+        // everything comes out in the order the sections were written
+        let unreachable: Vec<_> = graph
+            .nodes()
+            .filter(|identifier| !reachable.contains(identifier))
+            .cloned()
+            .collect();
+
+        let dead_ends: Vec<_> = graph
+            .nodes()
+            .filter(|identifier| !can_end.contains(identifier))
+            .cloned()
+            .collect();
+
+        let components = graph.components();
+        let component_of: HashMap<&SectionIdentifier, usize> = components
+            .iter()
+            .enumerate()
+            .flat_map(|(i, component)| component.iter().map(move |&identifier| (identifier, i)))
+            .collect();
+
+        // a loop is a component you can go round in, that has no choice
+        // leading out of it, and no ending in it
+        let mut loops: Vec<Vec<SectionIdentifier>> = components
+            .iter()
+            .enumerate()
+            .filter(|(i, component)| {
+                graph.is_cyclic(component)
+                    && component.iter().all(|identifier| {
+                        !self.sections()[*identifier].is_ending()
+                            && graph.edges[identifier]
+                                .iter()
+                                .all(|goto| component_of[goto] == *i)
+                    })
+            })
+            .map(|(_, component)| {
+                let mut component: Vec<_> = component.iter().map(|&id| id.clone()).collect();
+                component.sort_by_key(|identifier| self.sections().get_index_of(identifier));
+                component
+            })
+            .collect();
+        loops.sort_by_key(|component| self.sections().get_index_of(&component[0]));
+
+        // once they've all been taken there's nothing left to choose, which
+        // only matters if the section can be come back to
+        let exhaustible: Vec<_> = graph
+            .nodes()
+            .filter(|identifier| {
+                self.sections()[*identifier]
+                    .choices()
+                    .iter()
+                    .all(Choice::is_once)
+                    && graph.is_cyclic(&components[component_of[identifier]])
+            })
+            .cloned()
+            .collect();
+
+        let path_counts = graph.path_counts(self.metadata().start(), &components, &component_of);
+        let single_path: Vec<_> = graph
+            .nodes()
+            .filter(|identifier| {
+                path_counts.get(identifier) == Some(&1) && *identifier != self.metadata().start()
+            })
+            .cloned()
+            .collect();
+
+        Analysis {
+            unreachable,
+            dead_ends,
+            loops,
+            exhaustible,
+            single_path,
+        }
+    }
+}
+
+impl Analysis {
+    /// sections that can't be reached from the start section
+    pub fn unreachable(&self) -> &[SectionIdentifier] {
+        &self.unreachable
+    }
+
+    /// sections from which no choices ever lead to an ending
+    pub fn dead_ends(&self) -> &[SectionIdentifier] {
+        &self.dead_ends
+    }
+
+    /// groups of sections that lead only to each other, so once you're in,
+    /// you can never leave
+    pub fn loops(&self) -> &[Vec<SectionIdentifier>] {
+        &self.loops
+    }
+
+    /// sections whose choices are all once-only, that can be come back to
+    /// after they've all been taken, leaving nothing to choose
+    pub fn exhaustible(&self) -> &[SectionIdentifier] {
+        &self.exhaustible
+    }
+
+    /// sections that there's exactly one way of getting to from the start
+    pub fn single_path(&self) -> &[SectionIdentifier] {
+        &self.single_path
+    }
+
+    /// whether there's anything that's probably a mistake --- sections with a
+    /// single path to them are fine, so they don't count
+    pub fn has_problems(&self) -> bool {
+        !(self.unreachable.is_empty()
+            && self.dead_ends.is_empty()
+            && self.loops.is_empty()
+            && self.exhaustible.is_empty())
+    }
+}
+
+// the sections, and where their choices go, ignoring restarting and returning
+// to the menu
+struct Graph<'a> {
+    edges: IndexMap<&'a SectionIdentifier, Vec<&'a SectionIdentifier>>,
+}
+
+impl<'a> Graph<'a> {
+    fn new(story: &'a Story) -> Self {
+        let edges = story
+            .sections()
+            .iter()
+            .map(|(identifier, section)| {
+                let gotos = section
+                    .choices()
+                    .iter()
+                    .filter(|choice| !choice.is_special())
+                    .flat_map(Choice::gotos)
+                    .collect();
+                (identifier, gotos)
+            })
+            .collect();
+
+        Self { edges }
+    }
+
+    fn nodes(&self) -> impl Iterator<Item = &'a SectionIdentifier> + '_ {
+        self.edges.keys().copied()
+    }
+
+    fn reachable(&self, start: &SectionIdentifier) -> HashSet<&'a SectionIdentifier> {
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<_> = self.nodes().filter(|node| *node == start).collect();
+
+        while let Some(node) = queue.pop_front() {
+            if seen.insert(node) {
+                queue.extend(&self.edges[node]);
+            }
+        }
+        seen
+    }
+
+    // walks backwards from every ending
+    fn can_reach_ending(&self, story: &Story) -> HashSet<&'a SectionIdentifier> {
+        let mut reverse: HashMap<&SectionIdentifier, Vec<&SectionIdentifier>> = HashMap::new();
+        for (from, gotos) in &self.edges {
+            for to in gotos {
+                reverse.entry(to).or_default().push(from);
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<_> = self
+            .nodes()
+            .filter(|node| story.sections()[*node].is_ending())
+            .collect();
+
+        while let Some(node) = queue.pop_front() {
+            if seen.insert(node) {
+                queue.extend(reverse.get(node).into_iter().flatten());
+            }
+        }
+        seen
+    }
+
+    // strongly connected components, with tarjan's algorithm. they come out
+    // in reverse topological order, so every component is before any
+    // component that leads to it
+    fn components(&self) -> Vec<Vec<&'a SectionIdentifier>> {
+        struct State<'a> {
+            index: usize,
+            indices: HashMap<&'a SectionIdentifier, usize>,
+            low_links: HashMap<&'a SectionIdentifier, usize>,
+            stack: Vec<&'a SectionIdentifier>,
+            on_stack: HashSet<&'a SectionIdentifier>,
+            components: Vec<Vec<&'a SectionIdentifier>>,
+        }
+
+        fn connect<'a>(graph: &Graph<'a>, node: &'a SectionIdentifier, state: &mut State<'a>) {
+            state.indices.insert(node, state.index);
+            state.low_links.insert(node, state.index);
+            state.index += 1;
+            state.stack.push(node);
+            state.on_stack.insert(node);
+
+            for &next in &graph.edges[node] {
+                if !state.indices.contains_key(next) {
+                    connect(graph, next, state);
+                    let low_link = state.low_links[node].min(state.low_links[next]);
+                    state.low_links.insert(node, low_link);
+                } else if state.on_stack.contains(next) {
+                    let low_link = state.low_links[node].min(state.indices[next]);
+                    state.low_links.insert(node, low_link);
+                }
+            }
+
+            if state.low_links[node] == state.indices[node] {
+                let mut component = Vec::new();
+                loop {
+                    let member = state.stack.pop().expect("node is on the stack");
+                    state.on_stack.remove(member);
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                state.components.push(component);
+            }
+        }
+
+        let mut state = State {
+            index: 0,
+            indices: HashMap::new(),
+            low_links: HashMap::new(),
+            stack: Vec::new(),
+            on_stack: HashSet::new(),
+            components: Vec::new(),
+        };
+
+        for node in self.nodes() {
+            if !state.indices.contains_key(node) {
+                connect(self, node, &mut state);
+            }
+        }
+
+        state.components
+    }
+
+    // whether you can go round in circles within the component
+    fn is_cyclic(&self, component: &[&SectionIdentifier]) -> bool {
+        component.len() > 1 || self.edges[component[0]].contains(&component[0])
+    }
+
+    // how many distinct paths there are from the start to each reachable
+    // section, stopping counting at 2, since we only care whether there's one.
+    // going round a loop counts as another path
+    fn path_counts(
+        &self,
+        start: &SectionIdentifier,
+        components: &[Vec<&'a SectionIdentifier>],
+        component_of: &HashMap<&SectionIdentifier, usize>,
+    ) -> HashMap<&'a SectionIdentifier, usize> {
+        let mut counts: HashMap<&SectionIdentifier, usize> = HashMap::new();
+        let Some(start) = self.nodes().find(|node| *node == start) else {
+            return counts;
+        };
+        counts.insert(start, 1);
+
+        // components come out with the ones that are led to first, so go
+        // through them backwards to visit everything before what it leads to
+        for (i, component) in components.iter().enumerate().rev() {
+            // once you're in a loop, you can get anywhere in it any number
+            // of ways
+            if self.is_cyclic(component) && component.iter().any(|node| counts.contains_key(node)) {
+                for node in component {
+                    counts.insert(node, 2);
+                }
+            }
+
+            for node in component {
+                let Some(&count) = counts.get(node) else {
+                    continue;
+                };
+
+                for next in &self.edges[node] {
+                    // edges within a component are already accounted for
+                    if component_of[next] == i {
+                        continue;
+                    }
+                    let next_count = counts.entry(next).or_default();
+                    *next_count = (*next_count + count).min(2);
+                }
+            }
+        }
+
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identifiers(identifiers: &[&str]) -> Vec<SectionIdentifier> {
+        identifiers
+            .iter()
+            .map(|identifier| SectionIdentifier::parse((0, identifier), false).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn analyze() {
+        let story = Story::parse(
+            "\
+START:
+begin
+left -> LEFT
+right -> RIGHT
+loop -> LOOP_A
+---
+LEFT:
+left
+-> MIDDLE
+---
+RIGHT:
+right
+-> MIDDLE
+---
+MIDDLE:
+middle
+-> END
+---
+LOOP_A:
+a
+-> LOOP_B
+---
+LOOP_B:
+b
+-> LOOP_A
+---
+ORPHAN:
+nobody comes here
+-> END
+---",
+        )
+        .unwrap();
+
+        let analysis = story.analyze();
+        assert_eq!(analysis.unreachable(), identifiers(&["ORPHAN"]));
+        assert_eq!(analysis.dead_ends(), identifiers(&["LOOP_A", "LOOP_B"]));
+        assert_eq!(analysis.loops(), vec![identifiers(&["LOOP_A", "LOOP_B"])]);
+        assert_eq!(analysis.single_path(), identifiers(&["LEFT", "RIGHT"]));
+        assert!(analysis.exhaustible().is_empty());
+        assert!(analysis.has_problems());
+    }
+
+    #[test]
+    fn exhaustible() {
+        let story = Story::parse(
+            "\
+START:
+begin
+* look -> LOOK
+* leave -> STOP
+---
+LOOK:
+nothing here
+-> START
+---
+STOP:
+only once
+* stop -> END_IT
+---
+END_IT:
+the end
+-> END
+---",
+        )
+        .unwrap();
+
+        // STOP's choice is once-only too, but there's no coming back to it
+        assert_eq!(story.analyze().exhaustible(), identifiers(&["START"]));
+        assert!(story.analyze().has_problems());
+    }
+
+    #[test]
+    fn clean() {
+        let story = Story::parse(
+            "\
+START:
+begin
+round -> ROUND
+---
+ROUND:
+round again
+again -> START
+stop -> STOP
+---
+STOP:
+stop
+-> END
+---",
+        )
+        .unwrap();
+
+        let analysis = story.analyze();
+        assert!(!analysis.has_problems());
+        // you can go round as many times as you want before stopping
+        assert!(analysis.single_path().is_empty());
+    }
+}
@@ -0,0 +1,405 @@
+use crate::parse::{
+    Choice, Metadata, Outcome, ParseError, ParseErrorType, ParseResult, Section, SectionIdentifier,
+    Story,
+};
+
+/// builds a story in code rather than from text, with the same checks as
+/// [`Story::parse`]. there's no source for errors to point to, so they're all
+/// on line 0, with the identifier or section they're about as their text
+#[derive(Clone, Debug, Default)]
+pub struct StoryBuilder {
+    title: Option<String>,
+    author: Option<String>,
+    language: Option<String>,
+    version: Option<String>,
+    description: Option<String>,
+    start: Option<String>,
+    sections: Vec<SectionBuilder>,
+}
+
+/// a single section of a [`StoryBuilder`]
+#[derive(Clone, Debug)]
+pub struct SectionBuilder {
+    identifier: String,
+    paragraphs: Vec<String>,
+    /// empty descriptions are the `-> TARGET` shorthand, like in a story.
+    /// the flag is whether it's once-only
+    choices: Vec<(String, String, bool)>,
+}
+
+impl StoryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    pub fn language(mut self, language: impl Into<String>) -> Self {
+        self.language = Some(language.into());
+        self
+    }
+
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// the section the story starts at, which is `START` otherwise
+    pub fn start(mut self, start: impl Into<String>) -> Self {
+        self.start = Some(start.into());
+        self
+    }
+
+    /// adds a section, after any that have already been added
+    pub fn section(mut self, section: SectionBuilder) -> Self {
+        self.sections.push(section);
+        self
+    }
+
+    pub fn build(&self) -> ParseResult<Story> {
+        [
+            &self.title,
+            &self.author,
+            &self.language,
+            &self.version,
+            &self.description,
+        ]
+        .into_iter()
+        .flatten()
+        .try_for_each(|value| one_line(value))?;
+
+        let metadata = Metadata::new(
+            self.title.clone(),
+            self.author.clone(),
+            self.language.clone(),
+            self.version.clone(),
+            self.description.clone(),
+            self.start.as_deref().map(identifier).transpose()?,
+            0,
+        )?;
+
+        let sections = self
+            .sections
+            .iter()
+            .map(SectionBuilder::build)
+            .collect::<ParseResult<_>>()?;
+
+        Story::from_parts(metadata, sections)
+    }
+}
+
+impl SectionBuilder {
+    /// a section called `identifier`, which can be in a chapter, as in
+    /// `CHAPTER::NAME`
+    pub fn new(identifier: impl Into<String>) -> Self {
+        Self {
+            identifier: identifier.into(),
+            paragraphs: Vec::new(),
+            choices: Vec::new(),
+        }
+    }
+
+    /// a paragraph of the description, which can start with `[first]` or
+    /// `[again]`, like in a story. it can't be a lone `---`, since that would
+    /// end the section
+    pub fn paragraph(mut self, text: impl Into<String>) -> Self {
+        self.paragraphs.push(text.into());
+        self
+    }
+
+    /// a choice that goes to `goto`. unqualified gotos are resolved the same
+    /// way as in a story, so they can go to a section in the same chapter.
+    /// it can be random, as in `HEADS 50% | TAILS 50%`
+    pub fn choice(mut self, description: impl Into<String>, goto: impl Into<String>) -> Self {
+        self.choices.push((description.into(), goto.into(), false));
+        self
+    }
+
+    /// a choice like [`SectionBuilder::choice`], that goes away once it's been
+    /// taken, like `* take the key -> HALL`
+    pub fn once(mut self, description: impl Into<String>, goto: impl Into<String>) -> Self {
+        self.choices.push((description.into(), goto.into(), true));
+        self
+    }
+
+    /// `-> TARGET`, going straight on to another section
+    pub fn goto(self, goto: impl Into<String>) -> Self {
+        self.choice("", goto)
+    }
+
+    /// `-> END`, finishing the story here
+    pub fn end(self) -> Self {
+        self.goto("END")
+    }
+
+    fn build(&self) -> ParseResult<Section> {
+        let choices = self
+            .choices
+            .iter()
+            .map(|(description, goto, once)| {
+                one_line(description)?;
+                Ok(Choice::random(description, Outcome::parse_all((0, goto))?).with_once(*once))
+            })
+            .collect::<ParseResult<_>>()?;
+
+        // with a blank line between paragraphs, like in a story
+        let mut lines = Vec::new();
+        for (i, paragraph) in self.paragraphs.iter().enumerate() {
+            if i > 0 {
+                lines.push((0, ""));
+            }
+            lines.extend(paragraph.lines().map(|line| (0, line)));
+        }
+
+        Section::new(
+            identifier(&self.identifier)?,
+            &lines,
+            choices,
+            0,
+            &self.identifier,
+        )
+    }
+}
+
+fn identifier(str: &str) -> ParseResult<SectionIdentifier> {
+    SectionIdentifier::parse((0, str), false)
+}
+
+// metadata and choices are written on a line of their own, so anything on
+// the next line would be read as something else
+fn one_line(str: &str) -> ParseResult<()> {
+    if str.contains(['\n', '\r']) {
+        Err(ParseError::new(
+            ParseErrorType::LineBreak,
+            0,
+            str.to_owned(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{Paragraph, Shown};
+
+    #[test]
+    fn build() {
+        let story = StoryBuilder::new()
+            .title("The Lighthouse")
+            .author("Someone")
+            .language("en")
+            .version("1")
+            .description("A short one.")
+            .start("CH1::SHORE")
+            .section(SectionBuilder::new("HOME").paragraph("You go home.").end())
+            .section(
+                SectionBuilder::new("CH1::SHORE")
+                    .paragraph("The lamp's out.")
+                    .paragraph("Someone has to climb up.")
+                    // in the chapter, and out of it
+                    .choice("climb", "STAIRS")
+                    .choice("leave", "HOME"),
+            )
+            .section(
+                SectionBuilder::new("CH1::STAIRS")
+                    .paragraph("It's a long way.")
+                    .goto("LAMP"),
+            )
+            .section(
+                SectionBuilder::new("CH1::LAMP")
+                    .paragraph("You light it.")
+                    .end(),
+            )
+            .build()
+            .unwrap();
+
+        let parsed = Story::parse(
+            "\
+---
+title: The Lighthouse
+author: Someone
+language: en
+version: 1
+description: A short one.
+start: CH1::SHORE
+---
+HOME:
+You go home.
+-> END
+---
+chapter CH1
+SHORE:
+The lamp's out.
+
+Someone has to climb up.
+climb -> STAIRS
+leave -> HOME
+---
+STAIRS:
+It's a long way.
+-> LAMP
+---
+LAMP:
+You light it.
+-> END
+---",
+        )
+        .unwrap();
+
+        assert_eq!(story, parsed.without_locations());
+    }
+
+    #[test]
+    fn random() {
+        let story = StoryBuilder::new()
+            .section(
+                SectionBuilder::new("START")
+                    .paragraph("Heads or tails?")
+                    .choice("flip", "HEADS 20% | TAILS 80%"),
+            )
+            .section(SectionBuilder::new("HEADS").paragraph("Heads.").end())
+            .section(SectionBuilder::new("TAILS").paragraph("Tails.").end())
+            .build()
+            .unwrap();
+
+        let flip = &story.sections()[0].choices()[0];
+        assert!(flip.is_random());
+        assert_eq!(flip.target(), "HEADS 20% | TAILS 80%");
+
+        // the chances are checked like in a story
+        assert_eq!(
+            StoryBuilder::new()
+                .section(
+                    SectionBuilder::new("START")
+                        .paragraph("Heads or tails?")
+                        .choice("flip", "START 20% | START 20%"),
+                )
+                .build()
+                .unwrap_err()
+                .error_type()
+                .to_string(),
+            ParseErrorType::InvalidChances.to_string()
+        );
+    }
+
+    #[test]
+    fn visits() {
+        let story = StoryBuilder::new()
+            .section(
+                SectionBuilder::new("START")
+                    .paragraph("[first] You see a door.")
+                    .paragraph("[again] It's still shut.")
+                    .once("open it", "ROOM")
+                    .choice("wait", "START"),
+            )
+            .section(SectionBuilder::new("ROOM").paragraph("It's empty.").end())
+            .build()
+            .unwrap();
+
+        let start = &story.sections()[0];
+        let shown: Vec<Shown> = start
+            .description()
+            .paragraphs()
+            .iter()
+            .map(Paragraph::shown)
+            .collect();
+        assert_eq!(shown, [Shown::First, Shown::Again]);
+        assert!(start.choices()[0].is_once());
+        assert!(!start.choices()[1].is_once());
+
+        // only paragraphs come and go
+        assert_eq!(
+            StoryBuilder::new()
+                .section(
+                    SectionBuilder::new("START")
+                        .paragraph("hi")
+                        .choice("[first] go", "START"),
+                )
+                .build()
+                .unwrap_err()
+                .error_type()
+                .to_string(),
+            ParseErrorType::ShownChoice.to_string()
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |builder: StoryBuilder| builder.build().unwrap_err().error_type().to_string();
+        let start = || SectionBuilder::new("START").paragraph("Believe you can fly?");
+
+        assert_eq!(
+            error(StoryBuilder::new().section(start().choice("yes", "FLY"))),
+            ParseErrorType::DanglingGoto.to_string()
+        );
+        assert_eq!(
+            error(StoryBuilder::new().section(start().choice("yes", "fly"))),
+            ParseErrorType::SectionIdentifier('f').to_string()
+        );
+        assert_eq!(
+            error(StoryBuilder::new().section(start().goto("START").end())),
+            ParseErrorType::ChoiceShorthandNotLone.to_string()
+        );
+        assert_eq!(
+            error(StoryBuilder::new().section(SectionBuilder::new("START").end())),
+            ParseErrorType::ExpectedDescription.to_string()
+        );
+        assert_eq!(
+            error(StoryBuilder::new().section(start())),
+            ParseErrorType::ExpectedChoice.to_string()
+        );
+        assert_eq!(
+            error(StoryBuilder::new().start("END").section(start().end())),
+            ParseErrorType::ReservedKeyUsage.to_string()
+        );
+        assert_eq!(
+            error(StoryBuilder::new().section(SectionBuilder::new("FLY").paragraph("hi").end())),
+            ParseErrorType::MissingStart.to_string()
+        );
+        // nothing that couldn't be written as a story
+        assert_eq!(
+            error(StoryBuilder::new().section(start().paragraph("---").end())),
+            ParseErrorType::UnexpectedSeparator.to_string()
+        );
+        assert_eq!(
+            error(StoryBuilder::new().section(start().choice("yes -> no", "START"))),
+            ParseErrorType::MultipleArrows.to_string()
+        );
+        assert_eq!(
+            error(
+                StoryBuilder::new()
+                    .title("a\n---\nb")
+                    .section(start().end())
+            ),
+            ParseErrorType::LineBreak.to_string()
+        );
+        assert_eq!(
+            error(StoryBuilder::new().section(start().choice("yes\n\nreally", "START"))),
+            ParseErrorType::LineBreak.to_string()
+        );
+        assert!(matches!(
+            StoryBuilder::new()
+                .section(start().end())
+                .section(start().end())
+                .build()
+                .unwrap_err()
+                .error_type(),
+            ParseErrorType::DuplicateSections(..)
+        ));
+    }
+}
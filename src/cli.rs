@@ -1 +1,453 @@
-// TODO: clap stuff
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::SystemTime,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::{
+    export::{
+        epub, gamebook,
+        graph::{self, GraphOptions},
+        html, ink, markdown,
+    },
+    format, lsp,
+    parse::{self, DiskLoader, SectionIdentifier, Story},
+    refactor, script, walkthrough,
+};
+
+#[derive(Parser)]
+#[command(version, about = "Define and play choose-your-own-adventure stories")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check that a story parses
+    Check {
+        path: PathBuf,
+        /// Also warn about unreachable sections, dead ends, and loops
+        #[arg(long)]
+        lint: bool,
+    },
+    /// Rewrite stories in canonical form
+    Fmt {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Don't write anything, just fail if any story isn't formatted
+        #[arg(long)]
+        check: bool,
+    },
+    /// Play through a story following test scripts, checking it goes where
+    /// they expect
+    Test {
+        path: PathBuf,
+        #[arg(required = true)]
+        scripts: Vec<PathBuf>,
+    },
+    /// Show statistics about a story
+    Stats {
+        path: PathBuf,
+        /// Output as JSON rather than text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write out every way of playing through a story
+    Walkthrough {
+        path: PathBuf,
+        /// Stop after this many walkthroughs
+        #[arg(short, long, default_value_t = 1000)]
+        limit: usize,
+        /// Where to write the walkthroughs, rather than stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Convert a story to another format
+    Export {
+        path: PathBuf,
+        #[arg(short, long, value_enum)]
+        format: Format,
+        /// Where to write the result, rather than stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Group the sections of each chapter together, for story maps
+        #[arg(long)]
+        cluster_chapters: bool,
+        /// How to shuffle the numbered entries of gamebooks
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Show how two versions of a story differ, section by section
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+        /// Output as JSON rather than text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Rename a section, and every choice that goes to it, in a story and
+    /// the files it includes
+    Rename {
+        path: PathBuf,
+        old: String,
+        new: String,
+    },
+    /// Run a language server for editors, over stdin and stdout
+    Lsp,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// A graphviz digraph of the story map
+    Dot,
+    /// A mermaid flowchart of the story map
+    Mermaid,
+    /// The whole story as versioned JSON, for other tools
+    Json,
+    /// A single HTML page to play the story in, with everything inlined
+    Html,
+    /// An EPUB 3 book for e-readers, with choices as links between pages
+    Epub,
+    /// Twee 3, for Twine
+    Twee,
+    /// Markdown, with choices as links between section headings, for
+    /// reviewing on wikis and GitHub
+    Markdown,
+    /// Ink source, for game engines with an ink runtime
+    Ink,
+    /// A printable gamebook in Markdown, with shuffled, numbered entries
+    GamebookMarkdown,
+    /// A printable gamebook in plain text
+    GamebookText,
+    /// A single .fater file, with includes and chapters flattened
+    Fater,
+}
+
+pub fn run() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Check { path, lint } => check(&path, lint),
+        Command::Fmt { paths, check } => fmt(&paths, check),
+        Command::Test { path, scripts } => test(&path, &scripts),
+        Command::Stats { path, json } => stats(&path, json),
+        Command::Walkthrough {
+            path,
+            limit,
+            output,
+        } => walkthrough(&path, limit, output.as_deref()),
+        Command::Export {
+            path,
+            format,
+            output,
+            cluster_chapters,
+            seed,
+        } => export(&path, format, output.as_deref(), cluster_chapters, seed),
+        Command::Diff { old, new, json } => diff(&old, &new, json),
+        Command::Rename { path, old, new } => rename(&path, &old, &new),
+        Command::Lsp => lsp(),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(()) => ExitCode::FAILURE,
+    }
+}
+
+// errors are printed as they happen, so by the time we get an Err, all that's
+// left to do is exit with the right code
+type CliResult<T> = Result<T, ()>;
+
+fn load(path: &Path) -> CliResult<Story> {
+    // stories exported as json or twee can be read back in anywhere a story
+    // can
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    if let Some("json" | "twee" | "tw") = extension {
+        let source = fs::read_to_string(path)
+            .map_err(|err| eprintln!("error: couldn't read {}: {}", path.display(), err))?;
+
+        if extension == Some("json") {
+            return Story::from_json(&source)
+                .map_err(|err| eprintln!("error: {}: {}", path.display(), err));
+        }
+
+        let (story, warnings) = Story::from_twee(&source)
+            .map_err(|err| eprintln!("error: {}", err.in_file(Some(path))))?;
+        for warning in warnings {
+            eprintln!("warning: {}: {}", path.display(), warning);
+        }
+        return Ok(story);
+    }
+
+    match parse::load_file(path) {
+        Ok(Ok(story)) => Ok(story),
+        Ok(Err(err)) => {
+            eprintln!("error: {}", err);
+            Err(())
+        }
+        Err(err) => {
+            eprintln!("error: couldn't read {}: {}", path.display(), err);
+            Err(())
+        }
+    }
+}
+
+fn check(path: &Path, lint: bool) -> CliResult<()> {
+    let story = load(path)?;
+
+    if !lint {
+        return Ok(());
+    }
+
+    let analysis = story.analyze();
+    let start = story.metadata().start();
+
+    for identifier in analysis.unreachable() {
+        warn(
+            &story,
+            identifier,
+            &format!("can't be reached from {}", start),
+        );
+    }
+    for identifier in analysis.dead_ends() {
+        warn(&story, identifier, "can never reach an ending");
+    }
+    for sections in analysis.loops() {
+        let names: Vec<String> = sections.iter().map(ToString::to_string).collect();
+        warn(
+            &story,
+            &sections[0],
+            &format!("is in a loop with no way out: {}", names.join(", ")),
+        );
+    }
+    for identifier in analysis.exhaustible() {
+        warn(
+            &story,
+            identifier,
+            "can run out of choices, since they're all once-only",
+        );
+    }
+    for identifier in analysis.single_path() {
+        eprintln!(
+            "{}note: {} can only be reached one way",
+            location(&story, identifier),
+            identifier
+        );
+    }
+
+    if analysis.has_problems() {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+fn fmt(paths: &[PathBuf], check: bool) -> CliResult<()> {
+    let mut result = Ok(());
+
+    for path in paths {
+        let formatted = fs::read_to_string(path)
+            .map_err(|err| {
+                eprintln!("error: couldn't read {}: {}", path.display(), err);
+            })
+            .and_then(|source| {
+                format::format(&source)
+                    .map(|formatted| (formatted != source).then_some(formatted))
+                    .map_err(|err| {
+                        eprintln!("error: {}", err.in_file(Some(path)));
+                    })
+            });
+
+        match formatted {
+            Ok(None) => {}
+            Ok(Some(_)) if check => {
+                println!("{} isn't formatted", path.display());
+                result = Err(());
+            }
+            Ok(Some(formatted)) => {
+                if let Err(err) = fs::write(path, formatted) {
+                    eprintln!("error: couldn't write {}: {}", path.display(), err);
+                    result = Err(());
+                }
+            }
+            Err(()) => result = Err(()),
+        }
+    }
+
+    result
+}
+
+fn test(path: &Path, script_paths: &[PathBuf]) -> CliResult<()> {
+    let story = load(path)?;
+    let mut failed = 0;
+    let mut passed = 0;
+
+    for script_path in script_paths {
+        let scripts = fs::read_to_string(script_path)
+            .map_err(|err| {
+                eprintln!("error: couldn't read {}: {}", script_path.display(), err);
+            })
+            .and_then(|scripts| {
+                script::parse_scripts(&scripts).map_err(|err| {
+                    eprintln!("error: {}: {}", script_path.display(), err);
+                })
+            });
+        let Ok(scripts) = scripts else {
+            failed += 1;
+            continue;
+        };
+
+        for script in scripts {
+            match script.run(&story) {
+                Ok(()) => {
+                    passed += 1;
+                    println!("ok: {}", script.name());
+                }
+                Err(divergence) => {
+                    failed += 1;
+                    println!(
+                        "FAILED: {}\n    {}: {}",
+                        script.name(),
+                        script_path.display(),
+                        divergence
+                    );
+                }
+            }
+        }
+    }
+
+    println!("\n{} passed, {} failed", passed, failed);
+    if failed > 0 { Err(()) } else { Ok(()) }
+}
+
+fn stats(path: &Path, json: bool) -> CliResult<()> {
+    let stats = load(path)?.stats();
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&stats).expect("stats are always valid json")
+        );
+    } else {
+        print!("{}", stats);
+    }
+    Ok(())
+}
+
+fn walkthrough(path: &Path, limit: usize, output: Option<&Path>) -> CliResult<()> {
+    let story = load(path)?;
+    let playthroughs = story.playthroughs(limit);
+
+    write_output(output, walkthrough::walkthroughs(&story, &playthroughs))
+}
+
+fn export(
+    path: &Path,
+    format: Format,
+    output: Option<&Path>,
+    cluster_chapters: bool,
+    seed: u64,
+) -> CliResult<()> {
+    let story = load(path)?;
+
+    let options = GraphOptions {
+        cluster_chapters,
+        ..Default::default()
+    };
+    let exported = match format {
+        Format::Dot => graph::dot(&story, &options),
+        Format::Mermaid => graph::mermaid(&story, &options),
+        Format::Json => story.to_json(),
+        Format::Html => {
+            // images are relative to the story, like includes
+            let dir = path.parent().unwrap_or(Path::new(""));
+            html::html(&story, |name| fs::read(dir.join(name)))
+                .map_err(|err| eprintln!("error: {}", err))?
+        }
+        Format::Epub => {
+            let dir = path.parent().unwrap_or(Path::new(""));
+            let book = epub::epub(&story, |name| fs::read(dir.join(name)), SystemTime::now())
+                .map_err(|err| eprintln!("error: {}", err))?;
+            // the only one that isn't text
+            return write_output(output, book);
+        }
+        Format::Twee => {
+            let (twee, warnings) = story.to_twee();
+            for warning in warnings {
+                eprintln!("warning: {}", warning);
+            }
+            twee
+        }
+        Format::Fater => story.to_fater_string(),
+        Format::Markdown => markdown::markdown(&story),
+        Format::Ink => ink::ink(&story),
+        Format::GamebookMarkdown => gamebook::markdown(&story, seed),
+        Format::GamebookText => gamebook::text(&story, seed),
+    };
+
+    write_output(output, exported)
+}
+
+fn diff(old: &Path, new: &Path, json: bool) -> CliResult<()> {
+    let diff = load(old)?.diff(&load(new)?);
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&diff).expect("diffs are always valid json")
+        );
+    } else {
+        print!("{}", diff);
+    }
+    Ok(())
+}
+
+fn rename(path: &Path, old: &str, new: &str) -> CliResult<()> {
+    let renamed = refactor::rename_section_in_files(path, old, new, &mut DiskLoader)
+        .map_err(|err| eprintln!("error: couldn't rename {}: {}", old, err))?;
+
+    let mut result = Ok(());
+    for (path, source) in renamed {
+        if let Err(err) = fs::write(&path, source) {
+            eprintln!("error: couldn't write {}: {}", path.display(), err);
+            result = Err(());
+        }
+    }
+    result
+}
+
+fn lsp() -> CliResult<()> {
+    lsp::run().map_err(|err| eprintln!("error: {}", err))
+}
+
+// writes to the file if there is one, and stdout otherwise
+fn write_output(output: Option<&Path>, contents: impl AsRef<[u8]>) -> CliResult<()> {
+    match output {
+        Some(output) => fs::write(output, contents).map_err(|err| {
+            eprintln!("error: couldn't write {}: {}", output.display(), err);
+        }),
+        None => io::stdout()
+            .write_all(contents.as_ref())
+            .map_err(|err| eprintln!("error: couldn't write to stdout: {}", err)),
+    }
+}
+
+fn warn(story: &Story, identifier: &SectionIdentifier, message: &str) {
+    eprintln!(
+        "{}warning: {} {}",
+        location(story, identifier),
+        identifier,
+        message
+    );
+}
+
+// where a section's header is, in the same format as parse errors, for
+// prefixing
+fn location(story: &Story, identifier: &SectionIdentifier) -> String {
+    format!("{}: ", story.sections()[identifier].location())
+}
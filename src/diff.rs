@@ -0,0 +1,530 @@
+use std::{collections::HashSet, fmt::Display};
+
+use serde::Serialize;
+
+use crate::parse::{Choice, Section, SectionIdentifier, Story};
+
+/// what changed between two versions of a story, section by section rather
+/// than line by line
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StoryDiff {
+    added: Vec<SectionIdentifier>,
+    removed: Vec<SectionIdentifier>,
+    renamed: Vec<Renamed>,
+    changed: Vec<SectionChange>,
+    /// sections that can't be reached from the start any more, or new ones
+    /// that never could be
+    newly_unreachable: Vec<SectionIdentifier>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Renamed {
+    from: SectionIdentifier,
+    to: SectionIdentifier,
+}
+
+/// a section that's in both versions, under the new identifier if it was
+/// renamed
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SectionChange {
+    section: SectionIdentifier,
+    /// the old and new descriptions, if it changed
+    description: Option<(String, String)>,
+    added_choices: Vec<ChoiceSummary>,
+    removed_choices: Vec<ChoiceSummary>,
+    retargeted_choices: Vec<Retargeted>,
+    once_changed: Vec<OnceChanged>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ChoiceSummary {
+    description: String,
+    /// as it'd be written after the `->`, so with every outcome of a random
+    /// choice
+    goto: String,
+}
+
+/// a choice with the same text that goes somewhere else
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Retargeted {
+    description: String,
+    from: String,
+    to: String,
+}
+
+/// a choice with the same text that's become once-only, or sticky again
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct OnceChanged {
+    description: String,
+    once: bool,
+}
+
+impl Story {
+    /// how `new` differs from this story. a section that's gone, and one
+    /// that's appeared with the same description, are taken to be the same
+    /// section renamed. choices are matched up by their text
+    pub fn diff(&self, new: &Story) -> StoryDiff {
+        let mut removed: Vec<&Section> = self
+            .sections()
+            .values()
+            .filter(|section| !new.sections().contains_key(section.identifier()))
+            .collect();
+        let mut added: Vec<&Section> = new
+            .sections()
+            .values()
+            .filter(|section| !self.sections().contains_key(section.identifier()))
+            .collect();
+
+        let mut renamed = Vec::new();
+        removed.retain(|old| {
+            let same = added.iter().position(|new| {
+                !old.description().paragraphs().is_empty() && new.description() == old.description()
+            });
+            match same {
+                Some(i) => {
+                    renamed.push(Renamed {
+                        from: old.identifier().clone(),
+                        to: added.remove(i).identifier().clone(),
+                    });
+                    false
+                }
+                None => true,
+            }
+        });
+
+        // from an old identifier to what it's called now
+        let rename = |identifier: &SectionIdentifier| {
+            renamed
+                .iter()
+                .find(|renamed| renamed.from == *identifier)
+                .map_or(identifier, |renamed| &renamed.to)
+                .clone()
+        };
+
+        let changed = new
+            .sections()
+            .values()
+            .filter_map(|section| {
+                let old_identifier = renamed
+                    .iter()
+                    .find(|renamed| renamed.to == *section.identifier())
+                    .map_or(section.identifier(), |renamed| &renamed.from);
+                let old = self.sections().get(old_identifier)?;
+
+                let change = section_change(old, section, rename);
+                (change.description.is_some()
+                    || !change.added_choices.is_empty()
+                    || !change.removed_choices.is_empty()
+                    || !change.retargeted_choices.is_empty()
+                    || !change.once_changed.is_empty())
+                .then_some(change)
+            })
+            .collect();
+
+        let unreachable: HashSet<SectionIdentifier> =
+            self.analyze().unreachable().iter().map(rename).collect();
+        let newly_unreachable = new
+            .analyze()
+            .unreachable()
+            .iter()
+            .filter(|identifier| !unreachable.contains(identifier))
+            .cloned()
+            .collect();
+
+        StoryDiff {
+            added: added
+                .into_iter()
+                .map(|section| section.identifier().clone())
+                .collect(),
+            removed: removed
+                .into_iter()
+                .map(|section| section.identifier().clone())
+                .collect(),
+            renamed,
+            changed,
+            newly_unreachable,
+        }
+    }
+}
+
+fn section_change(
+    old: &Section,
+    new: &Section,
+    rename: impl Fn(&SectionIdentifier) -> SectionIdentifier,
+) -> SectionChange {
+    let description = (old.description() != new.description())
+        .then(|| (old.description().to_string(), new.description().to_string()));
+
+    let mut added_choices = Vec::new();
+    let mut retargeted_choices = Vec::new();
+    let mut once_changed = Vec::new();
+    // so each old choice is only matched up once
+    let mut matched = vec![false; old.choices().len()];
+
+    for choice in new.choices() {
+        let same = old
+            .choices()
+            .iter()
+            .enumerate()
+            .position(|(i, old)| !matched[i] && old.description() == choice.description());
+
+        match same {
+            Some(i) => {
+                matched[i] = true;
+                let from = &old.choices()[i];
+                // every way a random choice could go has to stay the same
+                let renamed: Vec<_> = from
+                    .outcomes()
+                    .iter()
+                    .map(|outcome| (rename(outcome.goto()), outcome.chance()))
+                    .collect();
+                let outcomes: Vec<_> = choice
+                    .outcomes()
+                    .iter()
+                    .map(|outcome| (outcome.goto().clone(), outcome.chance()))
+                    .collect();
+                if renamed != outcomes {
+                    retargeted_choices.push(Retargeted {
+                        description: choice.description().to_string(),
+                        from: from.target(),
+                        to: choice.target(),
+                    });
+                }
+                if from.is_once() != choice.is_once() {
+                    once_changed.push(OnceChanged {
+                        description: choice.description().to_string(),
+                        once: choice.is_once(),
+                    });
+                }
+            }
+            None => added_choices.push(ChoiceSummary::new(choice)),
+        }
+    }
+
+    let removed_choices = old
+        .choices()
+        .iter()
+        .zip(matched)
+        .filter(|(_, matched)| !matched)
+        .map(|(choice, _)| ChoiceSummary::new(choice))
+        .collect();
+
+    SectionChange {
+        section: new.identifier().clone(),
+        description,
+        added_choices,
+        removed_choices,
+        retargeted_choices,
+        once_changed,
+    }
+}
+
+impl StoryDiff {
+    pub fn added(&self) -> &[SectionIdentifier] {
+        &self.added
+    }
+
+    pub fn removed(&self) -> &[SectionIdentifier] {
+        &self.removed
+    }
+
+    pub fn renamed(&self) -> &[Renamed] {
+        &self.renamed
+    }
+
+    pub fn changed(&self) -> &[SectionChange] {
+        &self.changed
+    }
+
+    pub fn newly_unreachable(&self) -> &[SectionIdentifier] {
+        &self.newly_unreachable
+    }
+
+    /// whether the two versions mean the same thing
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.changed.is_empty()
+            && self.newly_unreachable.is_empty()
+    }
+}
+
+impl Renamed {
+    pub fn from(&self) -> &SectionIdentifier {
+        &self.from
+    }
+
+    pub fn to(&self) -> &SectionIdentifier {
+        &self.to
+    }
+}
+
+impl SectionChange {
+    pub fn section(&self) -> &SectionIdentifier {
+        &self.section
+    }
+
+    pub fn description(&self) -> Option<(&str, &str)> {
+        self.description
+            .as_ref()
+            .map(|(old, new)| (old.as_str(), new.as_str()))
+    }
+
+    pub fn added_choices(&self) -> &[ChoiceSummary] {
+        &self.added_choices
+    }
+
+    pub fn removed_choices(&self) -> &[ChoiceSummary] {
+        &self.removed_choices
+    }
+
+    pub fn retargeted_choices(&self) -> &[Retargeted] {
+        &self.retargeted_choices
+    }
+
+    pub fn once_changed(&self) -> &[OnceChanged] {
+        &self.once_changed
+    }
+}
+
+impl ChoiceSummary {
+    fn new(choice: &Choice) -> Self {
+        Self {
+            description: choice.description().to_string(),
+            goto: choice.target(),
+        }
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn goto(&self) -> &str {
+        &self.goto
+    }
+}
+
+impl Retargeted {
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn from(&self) -> &str {
+        &self.from
+    }
+
+    pub fn to(&self) -> &str {
+        &self.to
+    }
+}
+
+impl OnceChanged {
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// whether it's once-only now
+    pub fn is_once(&self) -> bool {
+        self.once
+    }
+}
+
+impl Display for StoryDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no changes");
+        }
+
+        for identifier in &self.added {
+            writeln!(f, "added {}", identifier)?;
+        }
+        for identifier in &self.removed {
+            writeln!(f, "removed {}", identifier)?;
+        }
+        for renamed in &self.renamed {
+            writeln!(f, "renamed {} to {}", renamed.from, renamed.to)?;
+        }
+
+        for change in &self.changed {
+            writeln!(f, "changed {}", change.section)?;
+
+            if let Some((old, new)) = &change.description {
+                writeln!(f, "    description:")?;
+                for line in old.lines().filter(|line| !line.is_empty()) {
+                    writeln!(f, "      - {}", line)?;
+                }
+                for line in new.lines().filter(|line| !line.is_empty()) {
+                    writeln!(f, "      + {}", line)?;
+                }
+            }
+            for choice in &change.added_choices {
+                writeln!(
+                    f,
+                    "    added choice \"{}\" -> {}",
+                    choice.description, choice.goto
+                )?;
+            }
+            for choice in &change.removed_choices {
+                writeln!(
+                    f,
+                    "    removed choice \"{}\" -> {}",
+                    choice.description, choice.goto
+                )?;
+            }
+            for choice in &change.retargeted_choices {
+                writeln!(
+                    f,
+                    "    retargeted choice \"{}\" from {} to {}",
+                    choice.description, choice.from, choice.to
+                )?;
+            }
+            for choice in &change.once_changed {
+                writeln!(
+                    f,
+                    "    made choice \"{}\" {}",
+                    choice.description,
+                    if choice.once { "once-only" } else { "sticky" }
+                )?;
+            }
+        }
+
+        for identifier in &self.newly_unreachable {
+            writeln!(f, "newly unreachable {}", identifier)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = "\
+START:
+Believe you can fly?
+yes -> FLY
+no -> CLIFF
+---
+CLIFF:
+You arrive at the cliffside.
+jump -> FLY
+go home -> START
+---
+FLY:
+You can't.
+-> END
+---";
+
+    // FLY is now JUMP, CLIFF can't be got to, and there's a new ending
+    const NEW: &str = "\
+START:
+Believe you can fly?
+Really?
+yes -> JUMP
+no -> JUMP
+maybe -> STAY
+---
+CLIFF:
+You arrive at the cliffside.
+jump -> JUMP
+---
+JUMP:
+You can't.
+-> END
+---
+STAY:
+You stay home.
+-> END
+---";
+
+    fn identifiers(identifiers: &[&str]) -> Vec<SectionIdentifier> {
+        identifiers
+            .iter()
+            .map(|identifier| SectionIdentifier::parse((0, identifier), false).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn diff() {
+        let old = Story::parse(OLD).unwrap();
+        let new = Story::parse(NEW).unwrap();
+        let diff = old.diff(&new);
+
+        assert_eq!(diff.added(), identifiers(&["STAY"]));
+        assert!(diff.removed().is_empty());
+        assert_eq!(diff.renamed().len(), 1);
+        assert_eq!(diff.renamed()[0].to(), &identifiers(&["JUMP"])[0]);
+        assert_eq!(diff.newly_unreachable(), identifiers(&["CLIFF"]));
+
+        let changed: Vec<&SectionIdentifier> =
+            diff.changed().iter().map(SectionChange::section).collect();
+        assert_eq!(
+            changed,
+            identifiers(&["START", "CLIFF"]).iter().collect::<Vec<_>>()
+        );
+
+        // going to FLY, now JUMP, isn't a change
+        let start = &diff.changed()[0];
+        assert!(start.description().is_some());
+        assert_eq!(start.added_choices()[0].description(), "maybe");
+        assert_eq!(start.retargeted_choices().len(), 1);
+        assert_eq!(start.retargeted_choices()[0].description(), "no");
+        assert_eq!(
+            diff.changed()[1].removed_choices()[0].description(),
+            "go home"
+        );
+
+        assert_eq!(
+            diff.to_string(),
+            "\
+added STAY
+renamed FLY to JUMP
+changed START
+    description:
+      - Believe you can fly?
+      + Believe you can fly? Really?
+    added choice \"maybe\" -> STAY
+    retargeted choice \"no\" from CLIFF to JUMP
+changed CLIFF
+    removed choice \"go home\" -> START
+newly unreachable CLIFF
+"
+        );
+    }
+
+    #[test]
+    fn once() {
+        let old = Story::parse(OLD).unwrap();
+        let new = Story::parse(&OLD.replace("no -> CLIFF", "* no -> CLIFF")).unwrap();
+        let diff = old.diff(&new);
+
+        assert!(!diff.is_empty());
+        let start = &diff.changed()[0];
+        assert!(start.added_choices().is_empty());
+        assert!(start.removed_choices().is_empty());
+        assert_eq!(start.once_changed()[0].description(), "no");
+        assert!(start.once_changed()[0].is_once());
+        assert_eq!(
+            diff.to_string(),
+            "changed START\n    made choice \"no\" once-only\n"
+        );
+
+        let diff = new.diff(&old);
+        assert!(!diff.changed()[0].once_changed()[0].is_once());
+        assert_eq!(
+            diff.to_string(),
+            "changed START\n    made choice \"no\" sticky\n"
+        );
+    }
+
+    #[test]
+    fn unchanged() {
+        let story = Story::parse(OLD).unwrap();
+        let diff = story.diff(&story);
+
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "no changes\n");
+    }
+}
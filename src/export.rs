@@ -0,0 +1,183 @@
+// turning a story into other formats, for viewing or for other tools
+
+use std::path::Path;
+
+use crate::parse::{Choice, Paragraph, SectionIdentifier, Shown, Story};
+
+pub mod epub;
+pub mod gamebook;
+pub mod graph;
+pub mod html;
+pub mod ink;
+pub mod markdown;
+
+// cuts text down to at most `max` characters, marking that it's been cut
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        text.to_owned()
+    } else {
+        let mut truncated: String = text.chars().take(max.saturating_sub(1)).collect();
+        truncated = truncated.trim_end().to_owned();
+        truncated.push('…');
+        truncated
+    }
+}
+
+// the parts of some text either side of each `[[image.png]]` in it, as the
+// text before each image with the image's name, and then whatever's left
+fn split_images(mut text: &str) -> (Vec<(&str, &str)>, &str) {
+    let mut images = Vec::new();
+    while let Some((before, rest)) = text.split_once("[[")
+        && let Some((name, after)) = rest.split_once("]]")
+    {
+        images.push((before, name));
+        text = after;
+    }
+    (images, text)
+}
+
+fn mime_type(name: &str) -> &'static str {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+// for html, and xml in general
+fn escape_html(str: &str) -> String {
+    str.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// anything that markdown would take for formatting gets a backslash, as does
+// anything at the start that would make it a heading, list or quote
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::new();
+    // `1.` or `1)` would start a numbered list
+    let digits = text.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(0);
+
+    for (i, ch) in text.chars().enumerate() {
+        if "\\`*_[]<>|".contains(ch)
+            || (i == 0 && "#+->".contains(ch))
+            || (i == digits && i > 0 && ".)".contains(ch))
+        {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+// the outcomes of a random choice, for formats that can only pick evenly
+// between things, so each is repeated in proportion to its chance
+pub(crate) fn evenly(choice: &Choice) -> Vec<&SectionIdentifier> {
+    let gcd = |mut a: u32, mut b: u32| {
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    };
+    let divisor = choice
+        .outcomes()
+        .iter()
+        .fold(0, |divisor, outcome| gcd(divisor, outcome.chance()));
+
+    choice
+        .outcomes()
+        .iter()
+        .flat_map(|outcome| {
+            std::iter::repeat_n(outcome.goto(), (outcome.chance() / divisor) as usize)
+        })
+        .collect()
+}
+
+// a page can't keep track of where the reader's been, so it leaves it to them:
+// what goes before a `[first]` or `[again]` paragraph
+fn visit_note(paragraph: &Paragraph) -> &'static str {
+    match paragraph.shown() {
+        Shown::Always => "",
+        Shown::First => "If this is your first time here: ",
+        Shown::Again => "If you've been here before: ",
+    }
+}
+
+// and after a once-only choice
+fn once_note(choice: &Choice) -> &'static str {
+    if choice.is_once() {
+        " (if you haven't already)"
+    } else {
+        ""
+    }
+}
+
+// a uuid for formats that want stories to have one. it's made from the story,
+// so exporting the same story twice gives the same one
+pub(crate) fn uuid(story: &Story) -> String {
+    let text = story.to_fater_string();
+    // fnv-1a, twice over, with different offsets
+    let hash = |offset: u64| {
+        text.bytes().fold(offset, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    };
+    let bytes = [hash(0xcbf29ce484222325), hash(0x84222325cbf29ce4)];
+    let mut bytes: Vec<u8> = bytes.iter().flat_map(|hash| hash.to_be_bytes()).collect();
+
+    // version 4, variant 1
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn truncate() {
+        assert_eq!(super::truncate("foo bar", 10), "foo bar");
+        assert_eq!(super::truncate("foo bar baz", 8), "foo bar…");
+    }
+
+    #[test]
+    fn split_images() {
+        assert_eq!(super::split_images("no images"), (vec![], "no images"));
+        assert_eq!(
+            super::split_images("a [[b.png]] c [[d.png]]"),
+            (vec![("a ", "b.png"), (" c ", "d.png")], "")
+        );
+        assert_eq!(super::split_images("[[unclosed"), (vec![], "[[unclosed"));
+    }
+
+    #[test]
+    fn escape_markdown() {
+        assert_eq!(super::escape_markdown("a *b* c"), "a \\*b\\* c");
+        assert_eq!(
+            super::escape_markdown("# not a heading"),
+            "\\# not a heading"
+        );
+        assert_eq!(super::escape_markdown("a # b - c"), "a # b - c");
+        assert_eq!(super::escape_markdown("1. not a list"), "1\\. not a list");
+        assert_eq!(super::escape_markdown("2004."), "2004\\.");
+        assert_eq!(super::escape_markdown("in 2004."), "in 2004.");
+    }
+}
@@ -0,0 +1,577 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Cursor, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use super::{escape_html as escape, mime_type, once_note, split_images, uuid, visit_note};
+use crate::parse::{Choice, Section, SectionIdentifier, Story};
+
+// the site's stylesheet is laid out for screens, so books get their own
+const CSS: &str = "\
+body {
+  font-family: serif;
+}
+
+ul.choices {
+  list-style: none;
+  padding: 0;
+  margin-top: 2em;
+}
+
+ul.choices li {
+  margin: 0.5em 0;
+}
+
+ul.choices li::before {
+  content: \"\u{2192} \";
+}
+
+p.end {
+  text-align: center;
+  font-style: italic;
+}
+
+img {
+  max-width: 100%;
+}
+";
+
+/// the story as an epub 3 book, with a page per section, and its choices as
+/// links between them. any `[[image.png]]` is read through `image`, by the
+/// name it's written with, and put in the book. `modified` is when the book
+/// says it was last changed
+pub fn epub(
+    story: &Story,
+    mut image: impl FnMut(&str) -> io::Result<Vec<u8>>,
+    modified: SystemTime,
+) -> io::Result<Vec<u8>> {
+    let mut book = Book::default();
+    let metadata = story.metadata();
+    let title = metadata.title().unwrap_or("Fater");
+    let language = metadata.language().unwrap_or("en");
+
+    // the title page, which the book opens on, and the menu goes back to
+    let mut body = format!("<h1>{}</h1>\n", escape(title));
+    if let Some(author) = metadata.author() {
+        writeln!(body, "<p>by {}</p>", escape(author)).unwrap();
+    }
+    if let Some(description) = metadata.description() {
+        writeln!(body, "<p>{}</p>", escape(description)).unwrap();
+    }
+    writeln!(
+        body,
+        "<ul class=\"choices\"><li><a href=\"sections/{}\">Begin</a></li></ul>",
+        file_name(metadata.start())
+    )
+    .unwrap();
+    let title_page = page(title, language, "fater.css", &body);
+
+    // the start comes straight after the title page, so reading on from it
+    // begins the story
+    let start = &story.sections()[metadata.start()];
+    let sections = std::iter::once(start).chain(
+        story
+            .sections()
+            .values()
+            .filter(|section| section.identifier() != metadata.start()),
+    );
+
+    let mut pages = Vec::new();
+    for section in sections {
+        let body = section_body(story, section, &mut book, &mut image)?;
+        pages.push((
+            file_name(section.identifier()),
+            page(
+                &section.identifier().to_string(),
+                language,
+                "../fater.css",
+                &body,
+            ),
+        ));
+    }
+
+    let nav = page(
+        title,
+        language,
+        "fater.css",
+        &format!(
+            "<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n<ol>\n\
+             <li><a href=\"title.xhtml\">{}</a></li>\n\
+             <li><a href=\"sections/{}\">Begin</a></li>\n</ol>\n</nav>\n\
+             <nav epub:type=\"landmarks\" hidden=\"\">\n<ol>\n\
+             <li><a epub:type=\"bodymatter\" href=\"sections/{}\">Begin</a></li>\n</ol>\n</nav>",
+            escape(title),
+            file_name(metadata.start()),
+            file_name(metadata.start())
+        ),
+    );
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    writeln!(
+        manifest,
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>"
+    )
+    .unwrap();
+    writeln!(
+        manifest,
+        "<item id=\"css\" href=\"fater.css\" media-type=\"text/css\"/>"
+    )
+    .unwrap();
+    writeln!(
+        manifest,
+        "<item id=\"title\" href=\"title.xhtml\" media-type=\"application/xhtml+xml\"/>"
+    )
+    .unwrap();
+    writeln!(spine, "<itemref idref=\"title\"/>").unwrap();
+    for (i, (file, _)) in pages.iter().enumerate() {
+        writeln!(
+            manifest,
+            "<item id=\"section-{}\" href=\"sections/{}\" media-type=\"application/xhtml+xml\"/>",
+            i, file
+        )
+        .unwrap();
+        writeln!(spine, "<itemref idref=\"section-{}\"/>", i).unwrap();
+    }
+    for (i, (name, file, _)) in book.images.iter().enumerate() {
+        writeln!(
+            manifest,
+            "<item id=\"image-{}\" href=\"images/{}\" media-type=\"{}\"/>",
+            i,
+            file,
+            mime_type(name)
+        )
+        .unwrap();
+    }
+
+    let mut package = String::new();
+    writeln!(package, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+    writeln!(
+        package,
+        "<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"id\" xml:lang=\"{}\">",
+        escape(language)
+    )
+    .unwrap();
+    writeln!(
+        package,
+        "<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">"
+    )
+    .unwrap();
+    writeln!(
+        package,
+        "<dc:identifier id=\"id\">urn:uuid:{}</dc:identifier>",
+        uuid(story)
+    )
+    .unwrap();
+    writeln!(package, "<dc:title>{}</dc:title>", escape(title)).unwrap();
+    writeln!(package, "<dc:language>{}</dc:language>", escape(language)).unwrap();
+    if let Some(author) = metadata.author() {
+        writeln!(package, "<dc:creator>{}</dc:creator>", escape(author)).unwrap();
+    }
+    if let Some(description) = metadata.description() {
+        writeln!(
+            package,
+            "<dc:description>{}</dc:description>",
+            escape(description)
+        )
+        .unwrap();
+    }
+    writeln!(
+        package,
+        "<meta property=\"dcterms:modified\">{}</meta>",
+        timestamp(modified)
+    )
+    .unwrap();
+    writeln!(package, "</metadata>").unwrap();
+    writeln!(package, "<manifest>\n{}</manifest>", manifest).unwrap();
+    writeln!(package, "<spine>\n{}</spine>", spine).unwrap();
+    writeln!(package, "</package>").unwrap();
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // readers go by the mimetype being first, and uncompressed
+    zip.start_file(
+        "mimetype",
+        SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    zip.write_all(b"application/epub+zip")?;
+
+    let mut files: Vec<(String, Vec<u8>)> = vec![
+        (
+            "META-INF/container.xml".to_owned(),
+            CONTAINER.as_bytes().to_vec(),
+        ),
+        ("OEBPS/content.opf".to_owned(), package.into_bytes()),
+        ("OEBPS/nav.xhtml".to_owned(), nav.into_bytes()),
+        ("OEBPS/fater.css".to_owned(), CSS.as_bytes().to_vec()),
+        ("OEBPS/title.xhtml".to_owned(), title_page.into_bytes()),
+    ];
+    files.extend(
+        pages
+            .into_iter()
+            .map(|(file, page)| (format!("OEBPS/sections/{}", file), page.into_bytes())),
+    );
+    files.extend(
+        book.images
+            .into_iter()
+            .map(|(_, file, data)| (format!("OEBPS/images/{}", file), data)),
+    );
+
+    for (path, data) in files {
+        zip.start_file(path, deflated)?;
+        zip.write_all(&data)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+const CONTAINER: &str = "\
+<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">
+<rootfiles>
+<rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>
+</rootfiles>
+</container>
+";
+
+// what's been gathered up while writing the pages
+#[derive(Default)]
+struct Book {
+    /// the name each image was written with, the file it's in in the book,
+    /// and what's in it
+    images: Vec<(String, String, Vec<u8>)>,
+}
+
+impl Book {
+    // the file an image is in, reading it the first time it's used
+    fn image(
+        &mut self,
+        name: &str,
+        image: &mut impl FnMut(&str) -> io::Result<Vec<u8>>,
+    ) -> io::Result<String> {
+        if let Some((_, file, _)) = self.images.iter().find(|(used, _, _)| used == name) {
+            return Ok(file.clone());
+        }
+
+        let data = image(name).map_err(|err| {
+            io::Error::new(err.kind(), format!("couldn't read image {}: {}", name, err))
+        })?;
+        // numbered, since the names could be anything
+        let extension = name.rsplit_once('.').map_or("", |(_, extension)| extension);
+        let file = format!("image{}.{}", self.images.len(), extension);
+        self.images.push((name.to_owned(), file.clone(), data));
+        Ok(file)
+    }
+}
+
+fn section_body(
+    story: &Story,
+    section: &Section,
+    book: &mut Book,
+    image: &mut impl FnMut(&str) -> io::Result<Vec<u8>>,
+) -> io::Result<String> {
+    let mut body = String::new();
+
+    for paragraph in section.description().paragraphs() {
+        writeln!(
+            body,
+            "<p>{}{}</p>",
+            visit_note(paragraph),
+            text(paragraph.text(), book, image)?
+        )
+        .unwrap();
+    }
+
+    if section.is_ending() {
+        writeln!(body, "<p class=\"end\">The end.</p>").unwrap();
+    }
+    writeln!(body, "<ul class=\"choices\">").unwrap();
+    for choice in section.choices() {
+        let label = text(&choice.label(), book, image)?;
+
+        if choice.is_random() {
+            // there's no telling whether a reader will run scripts, so it's
+            // left to whoever's reading, like in a gamebook
+            let outcomes: Vec<String> = choice
+                .outcomes()
+                .iter()
+                .map(|outcome| {
+                    format!(
+                        "<a href=\"{}\">{}%</a>",
+                        file_name(outcome.goto()),
+                        outcome.chance()
+                    )
+                })
+                .collect();
+            writeln!(
+                body,
+                "<li>{}{}: {}</li>",
+                label,
+                once_note(choice),
+                outcomes.join(" or ")
+            )
+            .unwrap();
+        } else {
+            writeln!(
+                body,
+                "<li><a href=\"{}\">{}</a>{}</li>",
+                choice_href(story, choice),
+                label,
+                once_note(choice)
+            )
+            .unwrap();
+        }
+    }
+    writeln!(body, "</ul>").unwrap();
+
+    Ok(body)
+}
+
+// from one section's page to wherever the choice goes
+fn choice_href(story: &Story, choice: &Choice) -> String {
+    match choice.goto().to_string().as_str() {
+        "__RESTART" => file_name(story.metadata().start()),
+        "__MENU" => "../title.xhtml".to_owned(),
+        _ => file_name(choice.goto()),
+    }
+}
+
+// escapes the text, apart from `[[image.png]]`, which becomes the image
+fn text(
+    text: &str,
+    book: &mut Book,
+    image: &mut impl FnMut(&str) -> io::Result<Vec<u8>>,
+) -> io::Result<String> {
+    let (images, rest) = split_images(text);
+    let mut html = String::new();
+
+    for (before, name) in images {
+        write!(
+            html,
+            "{}<img src=\"../images/{}\" alt=\"{}\"/>",
+            escape(before),
+            book.image(name, image)?,
+            escape(name)
+        )
+        .unwrap();
+    }
+    html.push_str(&escape(rest));
+
+    Ok(html)
+}
+
+// `::` can't be in a file name everywhere
+fn file_name(identifier: &SectionIdentifier) -> String {
+    format!("{}.xhtml", identifier.to_string().replace("::", "."))
+}
+
+fn page(title: &str, language: &str, css: &str, body: &str) -> String {
+    format!(
+        "\
+<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE html>
+<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"{language}\" lang=\"{language}\">
+<head>
+<title>{}</title>
+<link rel=\"stylesheet\" type=\"text/css\" href=\"{}\"/>
+</head>
+<body>
+{}
+</body>
+</html>
+",
+        escape(title),
+        css,
+        body.trim_end(),
+        language = escape(language)
+    )
+}
+
+// `2004-03-20T00:00:00Z`, in utc
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+
+    // days since the epoch to a date, from howard hinnant's `civil_from_days`
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Read, time::Duration};
+    use zip::ZipArchive;
+
+    const STORY: &str = "\
+---
+title: Fish & Chips
+author: Someone
+---
+START:
+You see a door.
+
+[[door.png]]
+open it -> CH1::ROOM
+---
+chapter CH1
+ROOM:
+It's empty, apart from [[door.png]].
+-> END
+---";
+
+    #[test]
+    fn epub() {
+        let story = Story::parse(STORY).unwrap();
+        let mut reads = 0;
+        let book = super::epub(
+            &story,
+            |name| {
+                assert_eq!(name, "door.png");
+                reads += 1;
+                Ok(b"door".to_vec())
+            },
+            UNIX_EPOCH,
+        )
+        .unwrap();
+        assert_eq!(reads, 1);
+
+        let mut zip = ZipArchive::new(Cursor::new(book)).unwrap();
+        let mut file = |name: &str| {
+            let mut text = String::new();
+            zip.by_name(name)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            text
+        };
+
+        assert_eq!(file("mimetype"), "application/epub+zip");
+        assert!(file("META-INF/container.xml").contains("OEBPS/content.opf"));
+
+        let package = file("OEBPS/content.opf");
+        assert!(package.contains("<dc:title>Fish &amp; Chips</dc:title>"));
+        assert!(package.contains("<dc:creator>Someone</dc:creator>"));
+        assert!(
+            package.contains("<meta property=\"dcterms:modified\">1970-01-01T00:00:00Z</meta>")
+        );
+        assert!(package.contains("href=\"images/image0.png\" media-type=\"image/png\""));
+        assert!(package.contains("<itemref idref=\"title\"/>\n<itemref idref=\"section-0\"/>"));
+
+        assert!(file("OEBPS/nav.xhtml").contains("<a href=\"sections/START.xhtml\">Begin</a>"));
+        assert!(file("OEBPS/title.xhtml").contains("<a href=\"sections/START.xhtml\">Begin</a>"));
+
+        let start = file("OEBPS/sections/START.xhtml");
+        assert!(start.contains("<p><img src=\"../images/image0.png\" alt=\"door.png\"/></p>"));
+        assert!(start.contains("<li><a href=\"CH1.ROOM.xhtml\">open it</a></li>"));
+
+        let room = file("OEBPS/sections/CH1.ROOM.xhtml");
+        assert!(room.contains("<p class=\"end\">The end.</p>"));
+        assert!(room.contains("<li><a href=\"START.xhtml\">Restart from beginning</a></li>"));
+        assert!(room.contains("<li><a href=\"../title.xhtml\">Return to menu</a></li>"));
+
+        assert_eq!(file("OEBPS/images/image0.png"), "door");
+    }
+
+    // the page for a section of a story without images
+    fn section(story: &str, identifier: &str) -> String {
+        let story = Story::parse(story).unwrap();
+        let book = super::epub(&story, |_| unreachable!(), UNIX_EPOCH).unwrap();
+
+        let mut text = String::new();
+        ZipArchive::new(Cursor::new(book))
+            .unwrap()
+            .by_name(&format!("OEBPS/sections/{}.xhtml", identifier))
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn random() {
+        let start = section(
+            "\
+START:
+Heads or tails?
+flip -> HEADS 20% | TAILS 80%
+---
+HEADS:
+Heads.
+-> END
+---
+TAILS:
+Tails.
+-> END
+---",
+            "START",
+        );
+
+        // a book can't roll a die, so it links to each way it can go
+        assert!(start.contains(
+            "<li>flip: <a href=\"HEADS.xhtml\">20%</a> or <a href=\"TAILS.xhtml\">80%</a></li>"
+        ));
+    }
+
+    #[test]
+    fn visits() {
+        let start = section(
+            "\
+START:
+[first] You see a door.
+
+[again] It's still shut.
+* open it -> ROOM
+wait -> START
+---
+ROOM:
+It's empty.
+-> END
+---",
+            "START",
+        );
+
+        assert!(start.contains("<p>If this is your first time here: You see a door.</p>"));
+        assert!(start.contains("<p>If you've been here before: It's still shut.</p>"));
+        assert!(
+            start.contains("<li><a href=\"ROOM.xhtml\">open it</a> (if you haven't already)</li>")
+        );
+        assert!(start.contains("<li><a href=\"START.xhtml\">wait</a></li>"));
+    }
+
+    #[test]
+    fn timestamp() {
+        assert_eq!(super::timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            super::timestamp(UNIX_EPOCH + Duration::from_secs(1079747106)),
+            "2004-03-20T01:45:06Z"
+        );
+        assert_eq!(
+            super::timestamp(UNIX_EPOCH + Duration::from_secs(951782400)),
+            "2000-02-29T00:00:00Z"
+        );
+    }
+}
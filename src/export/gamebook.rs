@@ -0,0 +1,305 @@
+use std::fmt::Write;
+
+use indexmap::IndexMap;
+
+use super::{escape_markdown, once_note, visit_note};
+use crate::{
+    parse::{Choice, Section, SectionIdentifier, Story},
+    runtime::Rng,
+};
+
+/// the number each section is printed under, from 1. the start is always 1,
+/// and the rest are shuffled, so the way through can't just be read off in
+/// order. the same seed always gives the same numbers
+pub fn numbers(story: &Story, seed: u64) -> IndexMap<SectionIdentifier, usize> {
+    let start = story.metadata().start();
+    let mut rest: Vec<&SectionIdentifier> = story
+        .sections()
+        .keys()
+        .filter(|identifier| *identifier != start)
+        .collect();
+
+    // fisher-yates
+    let mut rng = Rng::new(seed);
+    for i in (1..rest.len()).rev() {
+        let j = rng.below(i as u64 + 1) as usize;
+        rest.swap(i, j);
+    }
+
+    std::iter::once(start)
+        .chain(rest)
+        .enumerate()
+        .map(|(i, identifier)| (identifier.clone(), i + 1))
+        .collect()
+}
+
+/// the story as a gamebook in markdown, with a heading per numbered entry,
+/// and an index of which section each number is at the end
+pub fn markdown(story: &Story, seed: u64) -> String {
+    let numbers = numbers(story, seed);
+    let metadata = story.metadata();
+    let mut markdown = String::new();
+
+    if let Some(title) = metadata.title() {
+        writeln!(markdown, "# {}\n", escape_markdown(title)).unwrap();
+    }
+    if let Some(author) = metadata.author() {
+        writeln!(markdown, "*by {}*\n", escape_markdown(author)).unwrap();
+    }
+
+    for (identifier, number) in &numbers {
+        let section = &story.sections()[identifier];
+        writeln!(markdown, "## {}\n", number).unwrap();
+
+        for paragraph in section.description().paragraphs() {
+            writeln!(
+                markdown,
+                "{}{}\n",
+                visit_note(paragraph),
+                escape_markdown(paragraph.text())
+            )
+            .unwrap();
+        }
+        for choice in choices(section, &numbers) {
+            writeln!(markdown, "- {}", escape_markdown(&choice)).unwrap();
+        }
+        if section.is_ending() {
+            writeln!(markdown, "**The end.**").unwrap();
+        }
+        writeln!(markdown).unwrap();
+    }
+
+    writeln!(markdown, "## Index\n").unwrap();
+    writeln!(markdown, "| Number | Section |").unwrap();
+    writeln!(markdown, "| ---: | --- |").unwrap();
+    for (identifier, number) in sorted(&numbers) {
+        writeln!(markdown, "| {} | {} |", number, identifier).unwrap();
+    }
+
+    markdown
+}
+
+/// the story as a gamebook in plain text, for printing as-is
+pub fn text(story: &Story, seed: u64) -> String {
+    let numbers = numbers(story, seed);
+    let metadata = story.metadata();
+    let mut text = String::new();
+
+    if let Some(title) = metadata.title() {
+        writeln!(text, "{}", title.to_uppercase()).unwrap();
+    }
+    if let Some(author) = metadata.author() {
+        writeln!(text, "by {}", author).unwrap();
+    }
+    if metadata.title().is_some() || metadata.author().is_some() {
+        writeln!(text).unwrap();
+    }
+
+    for (identifier, number) in &numbers {
+        let section = &story.sections()[identifier];
+        if !text.is_empty() {
+            writeln!(text).unwrap();
+        }
+        writeln!(text, "{}\n", number).unwrap();
+
+        for paragraph in section.description().paragraphs() {
+            writeln!(text, "{}{}\n", visit_note(paragraph), paragraph.text()).unwrap();
+        }
+        for choice in choices(section, &numbers) {
+            writeln!(text, "    {}", choice).unwrap();
+        }
+        if section.is_ending() {
+            writeln!(text, "THE END").unwrap();
+        }
+    }
+
+    writeln!(text, "\n\nINDEX\n").unwrap();
+    let width = numbers.len().to_string().len();
+    for (identifier, number) in sorted(&numbers) {
+        writeln!(text, "{:>width$}  {}", number, identifier).unwrap();
+    }
+
+    text
+}
+
+// "label — turn to N" for every choice, apart from the ones an ending has
+fn choices(section: &Section, numbers: &IndexMap<SectionIdentifier, usize>) -> Vec<String> {
+    if section.is_ending() {
+        return Vec::new();
+    }
+
+    section
+        .choices()
+        .iter()
+        .map(|choice| {
+            format!(
+                "{}{} — turn to {}",
+                choice.label(),
+                once_note(choice),
+                turn_to(choice, numbers)
+            )
+        })
+        .collect()
+}
+
+// a random choice leaves it to the reader, with the chance of each, as in
+// "4 (30%) or 7 (70%)"
+fn turn_to(choice: &Choice, numbers: &IndexMap<SectionIdentifier, usize>) -> String {
+    if !choice.is_random() {
+        return numbers[choice.goto()].to_string();
+    }
+
+    let mut outcomes: Vec<String> = choice
+        .outcomes()
+        .iter()
+        .map(|outcome| format!("{} ({}%)", numbers[outcome.goto()], outcome.chance()))
+        .collect();
+    let last = outcomes.pop().expect("random choices have outcomes");
+    format!("{} or {}", outcomes.join(", "), last)
+}
+
+// the index is by number, so a reader can look up where they are
+fn sorted(numbers: &IndexMap<SectionIdentifier, usize>) -> Vec<(&SectionIdentifier, usize)> {
+    let mut sorted: Vec<_> = numbers
+        .iter()
+        .map(|(identifier, number)| (identifier, *number))
+        .collect();
+    sorted.sort_by_key(|(_, number)| *number);
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STORY: &str = "\
+---
+title: The Cliff
+---
+START:
+Believe you can fly?
+yes -> FLY
+no -> CLIFF
+---
+CLIFF:
+You arrive at the cliffside.
+jump -> FLY
+go home -> START
+---
+FLY:
+You can't.
+-> END
+---
+HOME:
+You never left.
+-> START
+---
+SHOP:
+It's closed.
+-> START
+---";
+
+    #[test]
+    fn numbers() {
+        let story = Story::parse(STORY).unwrap();
+        let numbers = super::numbers(&story, 1);
+
+        assert_eq!(numbers[story.metadata().start()], 1);
+        let mut sorted: Vec<usize> = numbers.values().copied().collect();
+        sorted.sort();
+        assert_eq!(sorted, [1, 2, 3, 4, 5]);
+
+        // the same every time, but not for every seed
+        assert_eq!(super::numbers(&story, 1), numbers);
+        assert!((2..10).any(|seed| super::numbers(&story, seed) != numbers));
+    }
+
+    #[test]
+    fn markdown() {
+        let story = Story::parse(STORY).unwrap();
+        let numbers = super::numbers(&story, 0);
+        let number =
+            |identifier: &str| numbers[&SectionIdentifier::parse((0, identifier), false).unwrap()];
+
+        let markdown = super::markdown(&story, 0);
+        assert!(markdown.starts_with("# The Cliff\n\n## 1\n\nBelieve you can fly?\n\n"));
+        assert!(markdown.contains(&format!(
+            "- yes — turn to {}\n- no — turn to {}\n",
+            number("FLY"),
+            number("CLIFF")
+        )));
+        assert!(markdown.contains(&format!(
+            "## {}\n\nYou can't.\n\n**The end.**\n",
+            number("FLY")
+        )));
+        assert!(markdown.contains("| 1 | START |\n"));
+        assert!(markdown.contains(&format!("| {} | SHOP |\n", number("SHOP"))));
+    }
+
+    #[test]
+    fn random() {
+        let story = Story::parse(
+            "\
+START:
+Heads or tails?
+flip -> HEADS 20% | TAILS 80%
+---
+HEADS:
+Heads.
+-> END
+---
+TAILS:
+Tails.
+-> END
+---",
+        )
+        .unwrap();
+        let numbers = super::numbers(&story, 0);
+        let number =
+            |identifier: &str| numbers[&SectionIdentifier::parse((0, identifier), false).unwrap()];
+
+        // the reader rolls for it
+        assert!(super::markdown(&story, 0).contains(&format!(
+            "- flip — turn to {} (20%) or {} (80%)\n",
+            number("HEADS"),
+            number("TAILS")
+        )));
+    }
+
+    #[test]
+    fn visits() {
+        let story = Story::parse(
+            "\
+START:
+[first] You see a door.
+
+[again] It's still shut.
+* open it -> ROOM
+wait -> START
+---
+ROOM:
+It's empty.
+-> END
+---",
+        )
+        .unwrap();
+        let numbers = super::numbers(&story, 0);
+        let number =
+            |identifier: &str| numbers[&SectionIdentifier::parse((0, identifier), false).unwrap()];
+
+        assert!(super::markdown(&story, 0).contains(&format!(
+            "## 1\n\nIf this is your first time here: You see a door.\n\nIf you've been here before: It's still shut.\n\n- open it (if you haven't already) — turn to {}\n- wait — turn to 1\n",
+            number("ROOM")
+        )));
+    }
+
+    #[test]
+    fn text() {
+        let story = Story::parse(STORY).unwrap();
+        let text = super::text(&story, 0);
+
+        assert!(text.starts_with("THE CLIFF\n\n\n1\n\nBelieve you can fly?\n\n    yes — turn to "));
+        assert!(text.contains("THE END\n"));
+        assert!(text.contains("\n\n\nINDEX\n\n1  START\n"));
+    }
+}
@@ -0,0 +1,407 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use super::truncate;
+use crate::parse::{Section, SectionIdentifier, Story};
+
+/// options for the story map exporters
+#[derive(Clone, Debug)]
+pub struct GraphOptions {
+    /// group the sections of each chapter together
+    pub cluster_chapters: bool,
+    /// how much of each section's description to put under its identifier
+    pub description_length: usize,
+}
+
+impl Default for GraphOptions {
+    fn default() -> Self {
+        Self {
+            cluster_chapters: false,
+            description_length: 40,
+        }
+    }
+}
+
+/// the story as a graphviz digraph, with a node per section and an edge per
+/// choice
+pub fn dot(story: &Story, options: &GraphOptions) -> String {
+    let map = StoryMap::new(story, options);
+    let mut dot = String::new();
+
+    writeln!(dot, "digraph {} {{", dot_string(map.title)).unwrap();
+    writeln!(dot, "    node [shape=box, style=rounded];").unwrap();
+
+    for (chapter, nodes) in map.chapters() {
+        let indent = if let Some(chapter) = chapter {
+            writeln!(
+                dot,
+                "    subgraph {} {{",
+                dot_string(&format!("cluster_{}", chapter))
+            )
+            .unwrap();
+            writeln!(dot, "        label={};", dot_string(chapter)).unwrap();
+            "        "
+        } else {
+            "    "
+        };
+
+        for node in nodes {
+            let style = match node.kind {
+                NodeKind::Start => ", style=\"rounded,bold\", penwidth=2",
+                NodeKind::Ending => ", shape=doubleoctagon, style=solid",
+                NodeKind::Unreachable => ", style=\"rounded,dashed\", color=gray, fontcolor=gray",
+                NodeKind::Normal => "",
+            };
+            writeln!(
+                dot,
+                "{}{} [label={}{}];",
+                indent,
+                dot_string(&node.section.identifier().to_string()),
+                dot_string(&node.label),
+                style
+            )
+            .unwrap();
+        }
+
+        if chapter.is_some() {
+            writeln!(dot, "    }}").unwrap();
+        }
+    }
+
+    for node in &map.nodes {
+        for (label, goto) in edges(node.section) {
+            writeln!(
+                dot,
+                "    {} -> {} [label={}];",
+                dot_string(&node.section.identifier().to_string()),
+                dot_string(&goto.to_string()),
+                dot_string(&label)
+            )
+            .unwrap();
+        }
+    }
+
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+/// the story as a mermaid flowchart, with a node per section and an edge per
+/// choice
+pub fn mermaid(story: &Story, options: &GraphOptions) -> String {
+    let map = StoryMap::new(story, options);
+    let mut mermaid = String::new();
+
+    writeln!(mermaid, "---\ntitle: {}\n---", yaml_string(map.title)).unwrap();
+    writeln!(mermaid, "flowchart TD").unwrap();
+
+    for (chapter, nodes) in map.chapters() {
+        let indent = if let Some(chapter) = chapter {
+            writeln!(mermaid, "    subgraph {}", mermaid_string(chapter)).unwrap();
+            "        "
+        } else {
+            "    "
+        };
+
+        for node in nodes {
+            writeln!(
+                mermaid,
+                "{}{}[\"{}\"]",
+                indent,
+                map.id(node.section.identifier()),
+                mermaid_string(&node.label).replace('\n', "<br>")
+            )
+            .unwrap();
+        }
+
+        if chapter.is_some() {
+            writeln!(mermaid, "    end").unwrap();
+        }
+    }
+
+    for node in &map.nodes {
+        for (label, goto) in edges(node.section) {
+            writeln!(
+                mermaid,
+                "    {} -->|\"{}\"| {}",
+                map.id(node.section.identifier()),
+                mermaid_string(&label),
+                map.id(goto)
+            )
+            .unwrap();
+        }
+    }
+
+    writeln!(mermaid, "    classDef start stroke-width:4px").unwrap();
+    writeln!(mermaid, "    classDef ending fill:#999999,stroke:#777777").unwrap();
+    writeln!(
+        mermaid,
+        "    classDef unreachable stroke-dasharray:5 5,color:#999999"
+    )
+    .unwrap();
+    for node in &map.nodes {
+        let class = match node.kind {
+            NodeKind::Start => "start",
+            NodeKind::Ending => "ending",
+            NodeKind::Unreachable => "unreachable",
+            NodeKind::Normal => continue,
+        };
+        writeln!(
+            mermaid,
+            "    class {} {}",
+            map.id(node.section.identifier()),
+            class
+        )
+        .unwrap();
+    }
+
+    mermaid
+}
+
+// an edge per choice, apart from restarting and returning to the menu, and
+// one for each outcome of a random choice, with its chance. once-only
+// choices say so
+fn edges(section: &Section) -> Vec<(String, &SectionIdentifier)> {
+    let mut edges = Vec::new();
+
+    for choice in section
+        .choices()
+        .iter()
+        .filter(|choice| !choice.is_special())
+    {
+        for outcome in choice.outcomes() {
+            let mut label = choice.label();
+            if choice.is_random() {
+                label = format!("{} ({}%)", label, outcome.chance());
+            }
+            if choice.is_once() {
+                label.push_str(" (once)");
+            }
+            edges.push((label, outcome.goto()));
+        }
+    }
+    edges
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NodeKind {
+    Start,
+    Ending,
+    Unreachable,
+    Normal,
+}
+
+struct Node<'a> {
+    section: &'a Section,
+    label: String,
+    kind: NodeKind,
+}
+
+// what both exporters need to know about the story, worked out once
+struct StoryMap<'a> {
+    title: &'a str,
+    nodes: Vec<Node<'a>>,
+    // where each section's node is, for numbering them
+    ids: HashMap<&'a SectionIdentifier, usize>,
+    cluster_chapters: bool,
+}
+
+impl<'a> StoryMap<'a> {
+    fn new(story: &'a Story, options: &GraphOptions) -> Self {
+        let analysis = story.analyze();
+        let unreachable: HashSet<_> = analysis.unreachable().iter().collect();
+
+        let nodes = story
+            .sections()
+            .values()
+            .map(|section| {
+                let identifier = section.identifier();
+                let kind = if identifier == story.metadata().start() {
+                    NodeKind::Start
+                } else if unreachable.contains(identifier) {
+                    NodeKind::Unreachable
+                } else if section.is_ending() {
+                    NodeKind::Ending
+                } else {
+                    NodeKind::Normal
+                };
+
+                let description = truncate(
+                    &section.description().to_string().replace("\n\n", " "),
+                    options.description_length,
+                );
+                let label = if description.is_empty() {
+                    identifier.to_string()
+                } else {
+                    format!("{}\n{}", identifier, description)
+                };
+
+                Node {
+                    section,
+                    label,
+                    kind,
+                }
+            })
+            .collect::<Vec<_>>();
+        let ids = nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (node.section.identifier(), index))
+            .collect();
+
+        Self {
+            title: story.metadata().title().unwrap_or("story"),
+            nodes,
+            ids,
+            cluster_chapters: options.cluster_chapters,
+        }
+    }
+
+    // the nodes grouped by chapter, with the ones outside of any chapter
+    // (or all of them, if we aren't clustering) first
+    fn chapters(&self) -> Vec<(Option<&'a str>, Vec<&Node<'a>>)> {
+        let mut chapters: Vec<(Option<&str>, Vec<&Node>)> = vec![(None, Vec::new())];
+
+        for node in &self.nodes {
+            let chapter = if self.cluster_chapters {
+                node.section.identifier().chapter()
+            } else {
+                None
+            };
+
+            match chapters.iter_mut().find(|(name, _)| *name == chapter) {
+                Some((_, nodes)) => nodes.push(node),
+                None => chapters.push((chapter, vec![node])),
+            }
+        }
+
+        chapters
+    }
+
+    // mermaid ids can't have colons in them, so number the sections instead
+    fn id(&self, identifier: &SectionIdentifier) -> String {
+        let index = self
+            .ids
+            .get(identifier)
+            .expect("gotos are validated when parsing");
+        format!("s{}", index)
+    }
+}
+
+fn dot_string(str: &str) -> String {
+    format!(
+        "\"{}\"",
+        str.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+fn mermaid_string(str: &str) -> String {
+    str.replace('"', "#quot;")
+}
+
+// the front matter is yaml, where a title like `Cliff: the sequel` would
+// otherwise be a mapping
+fn yaml_string(str: &str) -> String {
+    format!("\"{}\"", str.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STORY: &str = "\
+---
+title: Cliff
+---
+START:
+You arrive at the \"cliffside\". Believe you can fly?
+yes -> CH1::FLY
+no -> START
+---
+chapter CH1
+FLY:
+You can't.
+-> END
+---
+ORPHAN:
+nobody comes here
+-> END
+---";
+
+    #[test]
+    fn dot() {
+        let story = Story::parse(STORY).unwrap();
+        let dot = super::dot(
+            &story,
+            &GraphOptions {
+                cluster_chapters: true,
+                description_length: 18,
+            },
+        );
+
+        assert!(dot.starts_with("digraph \"Cliff\" {\n"));
+        assert!(dot.contains(
+            "    \"START\" [label=\"START\\nYou arrive at the…\", style=\"rounded,bold\", penwidth=2];"
+        ));
+        assert!(dot.contains("    subgraph \"cluster_CH1\" {\n        label=\"CH1\";\n"));
+        assert!(dot.contains("        \"CH1::ORPHAN\" [label=\"CH1::ORPHAN\\nnobody comes here\", style=\"rounded,dashed\""));
+        assert!(dot.contains("    \"START\" -> \"CH1::FLY\" [label=\"yes\"];"));
+        assert!(dot.contains("    \"START\" -> \"START\" [label=\"no\"];"));
+        // endings don't get edges to restarting
+        assert!(!dot.contains("__RESTART"));
+    }
+
+    #[test]
+    fn mermaid() {
+        let story = Story::parse(STORY).unwrap();
+        let mermaid = super::mermaid(&story, &GraphOptions::default());
+
+        // in the order they're written, so START is s0, CH1::FLY is s1, and
+        // CH1::ORPHAN is s2
+        assert!(mermaid.contains("    s1[\"CH1::FLY<br>You can't.\"]"));
+        assert!(mermaid.contains("    s0 -->|\"yes\"| s1"));
+        assert!(mermaid.contains("    class s0 start"));
+        assert!(mermaid.contains("    class s1 ending"));
+        assert!(mermaid.contains("    class s2 unreachable"));
+        assert!(!mermaid.contains("subgraph"));
+        assert!(mermaid.starts_with("---\ntitle: \"Cliff\"\n---\nflowchart TD\n"));
+
+        let story = Story::parse(&STORY.replace("Cliff", "Cliff: \"the\" \\ sequel")).unwrap();
+        let mermaid = super::mermaid(&story, &GraphOptions::default());
+        assert!(mermaid.starts_with("---\ntitle: \"Cliff: \\\"the\\\" \\\\ sequel\"\n---\n"));
+    }
+
+    #[test]
+    fn random() {
+        let story = Story::parse(
+            "\
+START:
+Heads or tails?
+flip -> HEADS 20% | TAILS 80%
+* cheat -> HEADS
+---
+HEADS:
+Heads.
+-> END
+---
+TAILS:
+Tails.
+-> END
+---",
+        )
+        .unwrap();
+
+        // an edge for each way a random choice can go
+        let dot = super::dot(&story, &GraphOptions::default());
+        assert!(dot.contains("    \"START\" -> \"HEADS\" [label=\"flip (20%)\"];"));
+        assert!(dot.contains("    \"START\" -> \"TAILS\" [label=\"flip (80%)\"];"));
+        assert!(dot.contains("    \"START\" -> \"HEADS\" [label=\"cheat (once)\"];"));
+
+        let mermaid = super::mermaid(&story, &GraphOptions::default());
+        assert!(mermaid.contains("    s0 -->|\"flip (20%)\"| s1"));
+        assert!(mermaid.contains("    s0 -->|\"cheat (once)\"| s1"));
+    }
+}
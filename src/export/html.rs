@@ -0,0 +1,340 @@
+use std::{fmt::Write, io};
+
+use super::{escape_html as escape, mime_type, split_images};
+use crate::parse::{Choice, SectionIdentifier, Shown, Story};
+
+// the same stylesheet as the site, so it looks the same
+const CSS: &str = include_str!("../../site/fater.css");
+
+// the sections are kept in templates, and swapped into the layout whenever the
+// hash changes, so choices can just be links. it keeps track of where the
+// reader's been, for `[first]` and `[again]` paragraphs and once-only choices
+const SCRIPT: &str = r#"let visits = {};
+let taken = new Set();
+function show() {
+  const id = decodeURIComponent(location.hash.slice(1));
+  const section = document.getElementById(id ? "section-" + id : "menu") || document.getElementById("menu");
+  const content = section.content.cloneNode(true);
+  const visit = visits[id] = (visits[id] || 0) + 1;
+  content.querySelectorAll("p[data-shown]").forEach((paragraph) => {
+    if ((paragraph.dataset.shown === "first") !== (visit === 1)) paragraph.remove();
+  });
+  content.querySelectorAll("a[data-once]").forEach((choice) => {
+    if (taken.has(choice.dataset.once)) choice.remove();
+  });
+  document.getElementById("layout").replaceChildren(content);
+}
+window.addEventListener("hashchange", show);
+document.addEventListener("click", (event) => {
+  const choice = event.target.closest("a.choice");
+  if (!choice) return;
+  event.preventDefault();
+  // starting over is a new playthrough
+  if ("begin" in choice.dataset) {
+    visits = {};
+    taken = new Set();
+  }
+  if (choice.dataset.once) taken.add(choice.dataset.once);
+  let id = decodeURIComponent(choice.getAttribute("href").slice(1));
+  // random choices pick where they go when they're clicked
+  if (choice.dataset.random) {
+    const outcomes = choice.dataset.random.split("|").map((outcome) => outcome.trim().split(" "));
+    let roll = Math.random() * 100;
+    [id] = outcomes.find(([, chance]) => (roll -= parseFloat(chance)) < 0) || outcomes[outcomes.length - 1];
+  }
+  // going back to the same section doesn't change the hash
+  if (decodeURIComponent(location.hash.slice(1)) === id) show();
+  else location.hash = id;
+});
+show();"#;
+
+/// the story as a single html page that can be opened straight from disk,
+/// with the stylesheet, and any `[[image.png]]` it shows, inlined. images are
+/// read through `image`, by the name they're written with
+pub fn html(
+    story: &Story,
+    mut image: impl FnMut(&str) -> io::Result<Vec<u8>>,
+) -> io::Result<String> {
+    let metadata = story.metadata();
+    let title = metadata.title().unwrap_or("Fater");
+    let mut html = String::new();
+
+    writeln!(html, "<!DOCTYPE html>").unwrap();
+    writeln!(
+        html,
+        "<html lang=\"{}\">",
+        escape(metadata.language().unwrap_or("en"))
+    )
+    .unwrap();
+    writeln!(html, "<head>").unwrap();
+    writeln!(html, "<meta charset=\"UTF-8\">").unwrap();
+    writeln!(
+        html,
+        "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">"
+    )
+    .unwrap();
+    writeln!(html, "<title>{}</title>", escape(title)).unwrap();
+    writeln!(html, "<style>\n{}", CSS.trim_end()).unwrap();
+    writeln!(
+        html,
+        "a.choice {{ color: inherit; text-decoration: none; }}"
+    )
+    .unwrap();
+    writeln!(html, "</style>").unwrap();
+    writeln!(html, "</head>").unwrap();
+    writeln!(html, "<body>").unwrap();
+    writeln!(html, "<div id=\"layout\"></div>").unwrap();
+
+    // the title screen, like on the site
+    let mut menu = escape(title);
+    if let Some(author) = metadata.author() {
+        write!(menu, "<br>by {}", escape(author)).unwrap();
+    }
+    if let Some(description) = metadata.description() {
+        write!(menu, "<br><br>{}", escape(description)).unwrap();
+    }
+    writeln!(html, "<template id=\"menu\">").unwrap();
+    writeln!(html, "<div id=\"description\"><p>{}</p></div>", menu).unwrap();
+    writeln!(
+        html,
+        "<div id=\"choices\"><a class=\"choice\" href=\"{}\" data-begin><span>Begin</span></a></div>",
+        href(metadata.start())
+    )
+    .unwrap();
+    writeln!(html, "</template>").unwrap();
+
+    for section in story.sections().values() {
+        writeln!(
+            html,
+            "<template id=\"section-{}\">",
+            escape(&section.identifier().to_string())
+        )
+        .unwrap();
+
+        writeln!(html, "<div id=\"description\">").unwrap();
+        for paragraph in section.description().paragraphs() {
+            let shown = match paragraph.shown() {
+                Shown::Always => "",
+                Shown::First => " data-shown=\"first\"",
+                Shown::Again => " data-shown=\"again\"",
+            };
+            writeln!(
+                html,
+                "<p{}>{}</p>",
+                shown,
+                text(paragraph.text(), &mut image)?
+            )
+            .unwrap();
+        }
+        writeln!(html, "</div>").unwrap();
+
+        writeln!(html, "<div id=\"choices\">").unwrap();
+        for (i, choice) in section.choices().iter().enumerate() {
+            let mut data = String::new();
+            if choice.is_random() {
+                write!(data, " data-random=\"{}\"", escape(&choice.target())).unwrap();
+            }
+            if choice.is_once() {
+                write!(
+                    data,
+                    " data-once=\"{} {}\"",
+                    escape(&section.identifier().to_string()),
+                    i
+                )
+                .unwrap();
+            }
+            if choice.goto().to_string() == "__RESTART" {
+                write!(data, " data-begin").unwrap();
+            }
+            writeln!(
+                html,
+                "<a class=\"choice\" href=\"{}\"{}><span>{}</span></a>",
+                choice_href(story, choice),
+                data,
+                text(&choice.label(), &mut image)?
+            )
+            .unwrap();
+        }
+        writeln!(html, "</div>").unwrap();
+
+        writeln!(html, "</template>").unwrap();
+    }
+
+    writeln!(html, "<script>\n{}\n</script>", SCRIPT).unwrap();
+    writeln!(html, "</body>").unwrap();
+    writeln!(html, "</html>").unwrap();
+
+    Ok(html)
+}
+
+fn href(identifier: &SectionIdentifier) -> String {
+    format!("#{}", escape(&identifier.to_string()))
+}
+
+fn choice_href(story: &Story, choice: &Choice) -> String {
+    match choice.goto().to_string().as_str() {
+        "__RESTART" => href(story.metadata().start()),
+        "__MENU" => "#".to_owned(),
+        _ => href(choice.goto()),
+    }
+}
+
+// escapes the text, apart from `[[image.png]]`, which becomes the image itself
+fn text(text: &str, image: &mut impl FnMut(&str) -> io::Result<Vec<u8>>) -> io::Result<String> {
+    let (images, rest) = split_images(text);
+    let mut html = String::new();
+
+    for (before, name) in images {
+        let data = image(name).map_err(|err| {
+            io::Error::new(err.kind(), format!("couldn't read image {}: {}", name, err))
+        })?;
+        write!(
+            html,
+            "{}<img src=\"data:{};base64,{}\" alt=\"{}\">",
+            escape(before),
+            mime_type(name),
+            base64(&data),
+            escape(name)
+        )
+        .unwrap();
+    }
+    html.push_str(&escape(rest));
+
+    Ok(html)
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STORY: &str = "\
+---
+title: Fish & Chips
+---
+START:
+You see a <door>.
+
+[[door.png]]
+open it -> CH1::ROOM
+---
+chapter CH1
+ROOM:
+It's empty.
+-> END
+---";
+
+    #[test]
+    fn html() {
+        let story = Story::parse(STORY).unwrap();
+        let html = super::html(&story, |name| {
+            assert_eq!(name, "door.png");
+            Ok(b"door".to_vec())
+        })
+        .unwrap();
+
+        assert!(html.contains("<title>Fish &amp; Chips</title>"));
+        assert!(html.contains(CSS.trim_end()));
+        assert!(
+            html.contains("<a class=\"choice\" href=\"#START\" data-begin><span>Begin</span></a>")
+        );
+        assert!(html.contains("<template id=\"section-START\">"));
+        assert!(html.contains("<p>You see a &lt;door&gt;.</p>"));
+        assert!(
+            html.contains("<p><img src=\"data:image/png;base64,ZG9vcg==\" alt=\"door.png\"></p>")
+        );
+        assert!(html.contains("<a class=\"choice\" href=\"#CH1::ROOM\"><span>open it</span></a>"));
+        // the ending goes back to the start, or the title screen
+        assert!(html.contains(
+            "<a class=\"choice\" href=\"#START\" data-begin><span>Restart from beginning</span></a>"
+        ));
+        assert!(html.contains("<a class=\"choice\" href=\"#\"><span>Return to menu</span></a>"));
+
+        let missing = super::html(&story, |_| Err(io::ErrorKind::NotFound.into()));
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn random() {
+        let story = Story::parse(
+            "\
+START:
+Heads or tails?
+flip -> HEADS 50% | TAILS 50%
+---
+HEADS:
+Heads.
+-> END
+---
+TAILS:
+Tails.
+-> END
+---",
+        )
+        .unwrap();
+        let html = super::html(&story, |_| Err(io::ErrorKind::NotFound.into())).unwrap();
+
+        // the script picks where it goes, and the link is for without it
+        assert!(html.contains(
+            "<a class=\"choice\" href=\"#HEADS\" data-random=\"HEADS 50% | TAILS 50%\"><span>flip</span></a>"
+        ));
+    }
+
+    #[test]
+    fn visits() {
+        let story = Story::parse(
+            "\
+START:
+[first] You see a door.
+
+[again] It's still there.
+* open it -> ROOM
+wait -> START
+---
+ROOM:
+It's empty.
+-> END
+---",
+        )
+        .unwrap();
+        let html = super::html(&story, |_| Err(io::ErrorKind::NotFound.into())).unwrap();
+
+        assert!(html.contains("<p data-shown=\"first\">You see a door.</p>"));
+        assert!(html.contains("<p data-shown=\"again\">It's still there.</p>"));
+        assert!(html.contains(
+            "<a class=\"choice\" href=\"#ROOM\" data-once=\"START 0\"><span>open it</span></a>"
+        ));
+        assert!(html.contains("<a class=\"choice\" href=\"#START\"><span>wait</span></a>"));
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(super::base64(b""), "");
+        assert_eq!(super::base64(b"f"), "Zg==");
+        assert_eq!(super::base64(b"fo"), "Zm8=");
+        assert_eq!(super::base64(b"foo"), "Zm9v");
+        assert_eq!(super::base64(b"foobar"), "Zm9vYmFy");
+    }
+}
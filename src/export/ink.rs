@@ -0,0 +1,182 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::parse::{Choice, SectionIdentifier, Shown, Story};
+
+/// the story as ink source, with a knot per section and an option per choice.
+/// once-only choices are once-only options (`*`), and the rest sticky (`+`).
+/// `[first]` and `[again]` paragraphs become sequences, which ink steps
+/// through each time the knot's visited
+pub fn ink(story: &Story) -> String {
+    let names = knot_names(story);
+    let mut ink = String::new();
+
+    // global tags, at the top of the file
+    let metadata = story.metadata();
+    for (key, value) in [
+        ("title", metadata.title()),
+        ("author", metadata.author()),
+        ("language", metadata.language()),
+        ("version", metadata.version()),
+        ("description", metadata.description()),
+    ] {
+        if let Some(value) = value {
+            writeln!(ink, "# {}: {}", key, escape(value)).unwrap();
+        }
+    }
+    writeln!(ink, "-> {}", names[metadata.start()]).unwrap();
+
+    for section in story.sections().values() {
+        writeln!(ink).unwrap();
+        writeln!(ink, "=== {} ===", names[section.identifier()]).unwrap();
+
+        for (i, paragraph) in section.description().paragraphs().iter().enumerate() {
+            if i > 0 {
+                writeln!(ink).unwrap();
+            }
+            let text = escape(paragraph.text());
+            match paragraph.shown() {
+                Shown::Always => writeln!(ink, "{}", text).unwrap(),
+                Shown::First => writeln!(ink, "{{{}|}}", text).unwrap(),
+                Shown::Again => writeln!(ink, "{{|{}}}", text).unwrap(),
+            }
+        }
+
+        if section.is_ending() {
+            writeln!(ink, "-> END").unwrap();
+            continue;
+        }
+        for choice in section.choices() {
+            let bullet = if choice.is_once() { '*' } else { '+' };
+            let label = escape(&choice.label());
+            if choice.is_random() {
+                writeln!(ink, "{} [{}] {}", bullet, label, shuffle(choice, &names)).unwrap();
+            } else {
+                writeln!(ink, "{} [{}] -> {}", bullet, label, names[choice.goto()]).unwrap();
+            }
+        }
+    }
+
+    ink
+}
+
+// ink names can only have letters, digits and underscores, so chapters are
+// joined on with `__`, and anything that then clashes, or is one of ink's own
+// names, gets a number on the end
+fn knot_names(story: &Story) -> HashMap<&SectionIdentifier, String> {
+    let mut names = HashMap::new();
+    let mut taken: HashSet<String> = ["END", "DONE"].map(str::to_owned).into();
+
+    for identifier in story.sections().keys() {
+        let mut name = identifier.to_string().replace("::", "__");
+        if name.starts_with(|ch: char| ch.is_ascii_digit()) {
+            name.insert(0, '_');
+        }
+
+        let mut unique = name.clone();
+        let mut i = 2;
+        while taken.contains(&unique) {
+            unique = format!("{}_{}", name, i);
+            i += 1;
+        }
+
+        taken.insert(unique.clone());
+        names.insert(identifier, unique);
+    }
+    names
+}
+
+// a random choice as an ink shuffle, as in `{~-> A|-> B|-> B}`
+fn shuffle(choice: &Choice, names: &HashMap<&SectionIdentifier, String>) -> String {
+    let diverts: Vec<String> = super::evenly(choice)
+        .into_iter()
+        .map(|goto| format!("-> {}", names[goto]))
+        .collect();
+    format!("{{~{}}}", diverts.join("|"))
+}
+
+// everything ink could take for markup gets a backslash, as does anything at
+// the start of a line that would make it a choice, gather or knot
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+
+    for (i, ch) in text.chars().enumerate() {
+        if "\\{}[]|#~<>/".contains(ch) || (i == 0 && "*+-=".contains(ch)) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ink() {
+        let story = Story::parse(
+            "\
+---
+title: The Cliff
+start: CLIFF
+---
+CLIFF:
+You arrive at the cliffside.
+
+[first] - Believe you can {fly}?
+
+[again] Still here?
+yes -> CH1::FLY
+* no // really -> CLIFF
+maybe -> CH1::FLY 25% | CLIFF 50% | DONE 25%
+---
+CH1__FLY:
+Not this one.
+-> CLIFF
+---
+DONE:
+Or this one.
+-> CLIFF
+---
+chapter CH1
+FLY:
+You can't.
+-> END
+---",
+        )
+        .unwrap();
+
+        assert_eq!(
+            super::ink(&story),
+            "\
+# title: The Cliff
+-> CLIFF
+
+=== CLIFF ===
+You arrive at the cliffside.
+
+{\\- Believe you can \\{fly\\}?|}
+
+{|Still here?}
++ [yes] -> CH1__FLY_2
+* [no \\/\\/ really] -> CLIFF
++ [maybe] {~-> CH1__FLY_2|-> CLIFF|-> CLIFF|-> DONE_2}
+
+=== CH1__FLY ===
+Not this one.
++ [Continue...] -> CLIFF
+
+=== DONE_2 ===
+Or this one.
++ [Continue...] -> CLIFF
+
+=== CH1__FLY_2 ===
+You can't.
+-> END
+"
+        );
+    }
+}
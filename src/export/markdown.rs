@@ -0,0 +1,248 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use super::{escape_markdown as escape, once_note, split_images, visit_note};
+use crate::parse::{Choice, SectionIdentifier, Story};
+
+/// the story as markdown, for reading through and reviewing, with a heading
+/// per section and its choices as links to the sections they go to. the links
+/// go to the anchors github, and most wikis, give headings
+pub fn markdown(story: &Story) -> String {
+    let metadata = story.metadata();
+    let anchors = Anchors::new(story);
+    let mut markdown = String::new();
+
+    if let Some(title) = metadata.title() {
+        writeln!(markdown, "# {}\n", escape(title)).unwrap();
+    }
+    if let Some(author) = metadata.author() {
+        writeln!(markdown, "*by {}*\n", escape(author)).unwrap();
+    }
+    if let Some(description) = metadata.description() {
+        writeln!(markdown, "{}\n", escape(description)).unwrap();
+    }
+
+    for section in story.sections().values() {
+        writeln!(
+            markdown,
+            "## {}\n",
+            escape(&section.identifier().to_string())
+        )
+        .unwrap();
+
+        for paragraph in section.description().paragraphs() {
+            writeln!(
+                markdown,
+                "{}{}\n",
+                visit_note(paragraph),
+                text(paragraph.text())
+            )
+            .unwrap();
+        }
+        if section.is_ending() {
+            writeln!(markdown, "**The end.**\n").unwrap();
+        }
+        for choice in section.choices() {
+            let label = text(&choice.label());
+
+            // a page can't roll a die, so random choices link to every way
+            // they could go
+            if choice.is_random() {
+                let outcomes: Vec<String> = choice
+                    .outcomes()
+                    .iter()
+                    .map(|outcome| {
+                        format!(
+                            "[{}%](#{})",
+                            outcome.chance(),
+                            anchors.sections[outcome.goto()]
+                        )
+                    })
+                    .collect();
+                writeln!(
+                    markdown,
+                    "- {}{}: {}",
+                    label,
+                    once_note(choice),
+                    outcomes.join(" or ")
+                )
+                .unwrap();
+            } else {
+                writeln!(
+                    markdown,
+                    "- [{}]({}){}",
+                    label,
+                    anchors.choice_href(story, choice),
+                    once_note(choice)
+                )
+                .unwrap();
+            }
+        }
+        if !section.choices().is_empty() {
+            writeln!(markdown).unwrap();
+        }
+    }
+
+    // no blank line at the very end
+    markdown.truncate(markdown.trim_end().len());
+    markdown.push('\n');
+    markdown
+}
+
+// where each heading can be linked to
+struct Anchors<'a> {
+    title: Option<String>,
+    sections: HashMap<&'a SectionIdentifier, String>,
+}
+
+impl<'a> Anchors<'a> {
+    fn new(story: &'a Story) -> Self {
+        // headings that come out the same get numbered, in the order they're
+        // in, so the title has to be counted too
+        let mut taken = HashSet::new();
+        let mut anchor = |heading: &str| {
+            let slug = slug(heading);
+            let mut unique = slug.clone();
+            let mut i = 1;
+            while !taken.insert(unique.clone()) {
+                unique = format!("{}-{}", slug, i);
+                i += 1;
+            }
+            unique
+        };
+
+        let title = story.metadata().title().map(&mut anchor);
+        let sections = story
+            .sections()
+            .keys()
+            .map(|identifier| (identifier, anchor(&identifier.to_string())))
+            .collect();
+
+        Self { title, sections }
+    }
+
+    fn choice_href(&self, story: &Story, choice: &Choice) -> String {
+        match choice.goto().to_string().as_str() {
+            "__RESTART" => format!("#{}", self.sections[story.metadata().start()]),
+            // the top of the page, if there isn't a title to go to
+            "__MENU" => format!("#{}", self.title.as_deref().unwrap_or("")),
+            _ => format!("#{}", self.sections[choice.goto()]),
+        }
+    }
+}
+
+// how github makes an anchor from a heading: lowercased, with spaces as
+// hyphens, and anything else that isn't a letter or digit left out
+fn slug(heading: &str) -> String {
+    heading
+        .to_lowercase()
+        .chars()
+        .filter_map(|ch| match ch {
+            ' ' => Some('-'),
+            '-' | '_' => Some(ch),
+            _ if ch.is_alphanumeric() => Some(ch),
+            _ => None,
+        })
+        .collect()
+}
+
+// escapes the text, apart from `[[image.png]]`, which becomes the image
+fn text(text: &str) -> String {
+    let (images, rest) = split_images(text);
+    let mut markdown = String::new();
+
+    for (before, name) in images {
+        write!(
+            markdown,
+            "{}![{}](<{}>)",
+            escape(before),
+            escape(name),
+            name
+        )
+        .unwrap();
+    }
+    markdown.push_str(&escape(rest));
+
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown() {
+        let story = Story::parse(
+            "\
+---
+title: The *Cliff*
+author: Someone
+---
+START:
+You arrive at the cliffside.
+
+ # [[cliff.png]]
+
+[first] First time?
+* jump -> CH1::FLY
+go home -> CH1FLY
+flip -> CH1::FLY 50% | START 50%
+---
+CH1FLY:
+Not this one.
+-> START
+---
+chapter CH1
+FLY:
+You can't.
+-> END
+---",
+        )
+        .unwrap();
+
+        assert_eq!(
+            super::markdown(&story),
+            "\
+# The \\*Cliff\\*
+
+*by Someone*
+
+## START
+
+You arrive at the cliffside.
+
+\\# ![cliff.png](<cliff.png>)
+
+If this is your first time here: First time?
+
+- [jump](#ch1fly-1) (if you haven't already)
+- [go home](#ch1fly)
+- flip: [50%](#ch1fly-1) or [50%](#start)
+
+## CH1FLY
+
+Not this one.
+
+- [Continue...](#start)
+
+## CH1::FLY
+
+You can't.
+
+**The end.**
+
+- [Restart from beginning](#start)
+- [Return to menu](#the-cliff)
+"
+        );
+    }
+
+    #[test]
+    fn slug() {
+        assert_eq!(super::slug("CH1::FLY"), "ch1fly");
+        assert_eq!(super::slug("The *Cliff*, again"), "the-cliff-again");
+        assert_eq!(super::slug("SNAKE_CASE"), "snake_case");
+    }
+}
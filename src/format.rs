@@ -0,0 +1,576 @@
+use std::io::{self, Write};
+
+use crate::{
+    parse::{self, Choice, Metadata, Outcome, ParseResult, Section, Story},
+    syntax::{Line, LineKind, Node, NodeKind, SyntaxTree},
+};
+
+/// re-emits a story in canonical form, without changing what it means:
+/// - the metadata header as `key: value`, followed by a blank line
+/// - each paragraph of a description on a single line, with a single blank
+///   line between paragraphs
+/// - choices as `description -> TARGET`, straight after the description
+/// - every section followed by `---`, with no blank lines between sections
+///
+/// comments are kept where they are. the file is checked for syntax errors,
+/// but includes aren't followed, and gotos aren't checked
+pub fn format(source: &str) -> ParseResult<String> {
+    let tree = SyntaxTree::parse(source);
+    let mut out: Vec<String> = Vec::new();
+
+    for node in tree.nodes() {
+        match node.kind() {
+            NodeKind::Trivia => format_trivia(&tree, node.lines(), &mut out),
+            NodeKind::Metadata => format_metadata(&tree, node, &mut out)?,
+            NodeKind::Include => {
+                let (num, line) = tree
+                    .parser_lines(node)
+                    .next()
+                    .expect("includes have a line");
+                let include = parse::parse_include(num, line).expect("it's an include line");
+                out.push(format!("include \"{}\"", include?));
+            }
+            NodeKind::Chapter => {
+                let (num, line) = tree
+                    .parser_lines(node)
+                    .next()
+                    .expect("chapters have a line");
+                let chapter = parse::parse_chapter(num, line).expect("it's a chapter line");
+                out.push(format!("chapter {}", chapter?));
+            }
+            NodeKind::Section => format_section(&tree, node, &mut out)?,
+        }
+    }
+
+    let mut formatted = out.join("\n");
+    formatted.push('\n');
+    Ok(formatted)
+}
+
+/// whether a story is already in canonical form
+pub fn is_formatted(source: &str) -> ParseResult<bool> {
+    Ok(format(source)? == source)
+}
+
+impl Story {
+    /// the story as `.fater` text, in the same canonical form as [`format`].
+    /// includes and chapters are flattened into one file, with every section
+    /// under its full identifier. parsing it again gives back the same story
+    pub fn to_fater_string(&self) -> String {
+        let mut out = Vec::new();
+        self.write_fater(&mut out)
+            .expect("writing to a vec doesn't fail");
+        String::from_utf8(out).expect("it's all written from strings")
+    }
+
+    /// writes the story as `.fater` text, like [`Story::to_fater_string`]
+    pub fn write_fater(&self, out: &mut impl Write) -> io::Result<()> {
+        let metadata = self.metadata();
+        let mut header: Vec<String> = [
+            ("title", metadata.title()),
+            ("author", metadata.author()),
+            ("language", metadata.language()),
+            ("version", metadata.version()),
+            ("description", metadata.description()),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some(format!("{}: {}", key, value?)))
+        .collect();
+        if metadata.start().to_string() != "START" {
+            header.push(format!("start: {}", metadata.start()));
+        }
+        if !header.is_empty() {
+            writeln!(out, "---")?;
+            for line in header {
+                writeln!(out, "{}", line)?;
+            }
+            writeln!(out, "---")?;
+            writeln!(out)?;
+        }
+
+        for section in self.sections().values() {
+            writeln!(out, "{}:", section.identifier())?;
+
+            for (i, paragraph) in section.description().paragraphs().iter().enumerate() {
+                if i > 0 {
+                    writeln!(out)?;
+                }
+                for line in paragraph_lines(&paragraph.to_string()) {
+                    writeln!(out, "{}", line)?;
+                }
+            }
+
+            // the shorthands get expanded when parsing, so they're collapsed
+            // back down again
+            match section.choices() {
+                _ if section.is_ending() => writeln!(out, "-> END")?,
+                [choice]
+                    if choice.description().to_string() == "Continue..." && !choice.is_once() =>
+                {
+                    writeln!(out, "-> {}", choice.target())?
+                }
+                choices => {
+                    for choice in choices {
+                        let description = choice.description().to_string();
+                        // a sticky choice that looks like it has a `*` has to
+                        // say it's sticky
+                        let marker = if choice.is_once() {
+                            "* "
+                        } else if split_marker(&description).is_some() {
+                            "+ "
+                        } else {
+                            ""
+                        };
+                        let choice = format!("{}{} -> {}", marker, description, choice.target());
+                        writeln!(out, "{}", text_line(&choice))?;
+                    }
+                }
+            }
+            writeln!(out, "---")?;
+        }
+
+        Ok(())
+    }
+}
+
+// a paragraph that would be read as a choice on one line, which can happen
+// when it was written over several, gets split after its arrow instead
+fn paragraph_lines(text: &str) -> Vec<String> {
+    match text.split_once("->") {
+        Some((before, after)) if Choice::parse(text, 0).is_ok() => {
+            vec![text_line(&format!("{}->", before)), text_line(after.trim())]
+        }
+        _ => vec![text_line(text)],
+    }
+}
+
+// anything that isn't a comment, but would look like one once trimmed, has to
+// keep some indentation
+fn text_line(text: &str) -> String {
+    if text.starts_with('#') {
+        format!(" {}", text)
+    } else {
+        text.to_owned()
+    }
+}
+
+// keeps comments, and drops blank lines
+fn format_trivia(tree: &SyntaxTree, lines: &[Line], out: &mut Vec<String>) {
+    for line in lines {
+        if line.kind() == LineKind::Comment {
+            out.push(tree.line_text(line).trim_end().to_owned());
+        }
+    }
+}
+
+fn format_metadata(tree: &SyntaxTree, node: &Node, out: &mut Vec<String>) -> ParseResult<()> {
+    Metadata::parse(&mut tree.parser_lines(node).peekable())?;
+
+    out.push("---".to_owned());
+    for line in node.lines() {
+        match line.kind() {
+            LineKind::Comment => format_trivia(tree, std::slice::from_ref(line), out),
+            LineKind::MetadataEntry => {
+                let (key, value) = tree
+                    .line_text(line)
+                    .split_once(':')
+                    .expect("checked by parsing it");
+                out.push(format!("{}: {}", key.trim(), value.trim()));
+            }
+            _ => {}
+        }
+    }
+    out.push("---".to_owned());
+    out.push(String::new());
+
+    Ok(())
+}
+
+fn format_section(tree: &SyntaxTree, node: &Node, out: &mut Vec<String>) -> ParseResult<()> {
+    Section::parse(tree.parser_lines(node))?;
+
+    // the lines of the paragraph we're in the middle of
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut blank = false;
+    let mut described = false;
+    let mut reached_choices = false;
+
+    for line in node.lines() {
+        let text = tree.line_text(line).trim();
+
+        match line.kind() {
+            LineKind::Header => out.push(text.to_owned()),
+            LineKind::Comment => {
+                flush_paragraph(&mut paragraph, out);
+                if blank {
+                    out.push(String::new());
+                    blank = false;
+                }
+                format_trivia(tree, std::slice::from_ref(line), out);
+            }
+            LineKind::Blank if !reached_choices => {
+                flush_paragraph(&mut paragraph, out);
+                // only a break between paragraphs once there's been one, and
+                // it only gets written if another one follows
+                blank = described;
+            }
+            LineKind::Choice => {
+                flush_paragraph(&mut paragraph, out);
+                blank = false;
+                reached_choices = true;
+                out.push(format_choice(text));
+            }
+            LineKind::Description => {
+                if blank {
+                    out.push(String::new());
+                    blank = false;
+                }
+                paragraph.push(text);
+                described = true;
+            }
+            _ => {}
+        }
+    }
+    flush_paragraph(&mut paragraph, out);
+    out.push("---".to_owned());
+
+    Ok(())
+}
+
+// puts a paragraph's lines back together on one line, unless that would turn
+// them into a choice
+fn flush_paragraph(paragraph: &mut Vec<&str>, out: &mut Vec<String>) {
+    if paragraph.is_empty() {
+        return;
+    }
+
+    let joined = paragraph.join(" ");
+    if Choice::parse(&joined, 0).is_err() {
+        out.push(text_line(&joined));
+    } else {
+        out.extend(paragraph.iter().map(|line| text_line(line)));
+    }
+    paragraph.clear();
+}
+
+// works on the text rather than the parsed choice, so the shorthands stay as
+// they were written
+fn format_choice(line: &str) -> String {
+    let (description, goto) = line.split_once("->").expect("a parsed choice has an arrow");
+    let (description, goto) = (description.trim(), goto.trim());
+
+    // the outcomes of a random choice get spaced out the same way
+    let goto = match Outcome::parse_all((0, goto)) {
+        Ok(outcomes) if goto.contains('%') => {
+            let outcomes: Vec<String> = outcomes.iter().map(Outcome::to_string).collect();
+            outcomes.join(" | ")
+        }
+        _ => goto.to_owned(),
+    };
+
+    // with just the one space after a `*` or `+`
+    let description = match split_marker(description) {
+        Some((marker, rest)) => format!("{} {}", marker, rest.trim_start()),
+        None => description.to_owned(),
+    };
+
+    if description.is_empty() {
+        format!("-> {}", goto)
+    } else {
+        text_line(&format!("{} -> {}", description, goto))
+    }
+}
+
+// the `*` or `+` at the start of a choice, and the rest of it, like the
+// parser sees them
+fn split_marker(description: &str) -> Option<(&str, &str)> {
+    match description.split_at_checked(1) {
+        Some((marker @ ("*" | "+"), rest)) if rest.starts_with(char::is_whitespace) => {
+            Some((marker, rest))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::MemoryLoader;
+    use proptest::prelude::*;
+    use std::path::Path;
+
+    #[test]
+    fn format() {
+        let source = "\
+# a comment
+
+---
+title:The Cliff
+  author :  Someone
+---
+START:
+  You arrive at the cliffside.
+Believe you can fly?
+
+
+ # indented, so not a comment
+
+# a real comment
+ and another paragraph
+yes->FLY
+*   once->FLY
+
+don't pull ->  NUCLEAR
+flip->FLY 50%|NUCLEAR  50%
+---
+
+
+FLY:
+You can't.
+-> END
+---
+include \"other.fater\"
+NUCLEAR:
+boom
+-> END";
+
+        let formatted = super::format(source).unwrap();
+        assert_eq!(
+            formatted,
+            "\
+# a comment
+---
+title: The Cliff
+author: Someone
+---
+
+START:
+You arrive at the cliffside. Believe you can fly?
+
+ # indented, so not a comment
+
+# a real comment
+and another paragraph
+yes -> FLY
+* once -> FLY
+don't pull -> NUCLEAR
+flip -> FLY 50% | NUCLEAR 50%
+---
+FLY:
+You can't.
+-> END
+---
+include \"other.fater\"
+NUCLEAR:
+boom
+-> END
+---
+"
+        );
+
+        assert!(is_formatted(&formatted).unwrap());
+        assert!(!is_formatted(source).unwrap());
+
+        // it still means the same thing, without the include
+        let story = |source: &str| {
+            Story::parse(&source.replace("include \"other.fater\"\n", ""))
+                .unwrap()
+                .without_locations()
+        };
+        assert_eq!(story(source), story(&formatted));
+    }
+
+    #[test]
+    fn joined_choice() {
+        // joining these would make a choice, so they're left alone
+        assert_eq!(
+            super::format("START:\ngo ->\nFOO\nok -> START\n---").unwrap(),
+            "START:\ngo ->\nFOO\nok -> START\n---\n"
+        );
+    }
+
+    #[test]
+    fn to_fater_string() {
+        let source = "\
+---
+title: The Cliff
+start: CLIFF
+---
+include \"fly.fater\"
+# a comment
+CLIFF:
+You arrive
+at the cliffside.
+
+[again]  Back again.
+
+Believe you can fly?
+yes -> CH1::FLY
+*  no -> CLIFF
++ * maybe -> CLIFF
+---";
+        let mut loader = MemoryLoader::default();
+        loader.insert(
+            "fly.fater",
+            "chapter CH1\nFLY:\n  #not a comment\n-> END\n---".to_owned(),
+        );
+        let story = Story::parse_with(source, Some(Path::new("main.fater")), &mut loader).unwrap();
+
+        let written = story.to_fater_string();
+        assert_eq!(
+            written,
+            "\
+---
+title: The Cliff
+start: CLIFF
+---
+
+CH1::FLY:
+ #not a comment
+-> END
+---
+CLIFF:
+You arrive at the cliffside.
+
+[again] Back again.
+
+Believe you can fly?
+yes -> CH1::FLY
+* no -> CLIFF
++ * maybe -> CLIFF
+---
+"
+        );
+        assert!(is_formatted(&written).unwrap());
+        assert_eq!(Story::parse(&written).unwrap().to_fater_string(), written);
+    }
+
+    #[test]
+    fn shorthands() {
+        let source = "START:\ngo ->\nFOO\n-> FLY\n---\nFLY:\nYou can't.\n-> END\n---\n";
+        let story = Story::parse(source).unwrap();
+        assert_eq!(story.to_fater_string(), source);
+
+        // written out in full, it means the same as the shorthand
+        let story = Story::parse(&source.replace("-> FLY", "Continue... -> FLY")).unwrap();
+        assert_eq!(story.to_fater_string(), source);
+    }
+
+    const NAMES: [&str; 4] = ["START", "CLIFF", "CH1::FLY", "FLY"];
+
+    // the lines of a description, including ones that only look like choices
+    // or comments once they've been put together
+    fn description_line() -> impl Strategy<Value = &'static str> {
+        prop::sample::select(vec![
+            "You arrive at the cliffside.",
+            "  Believe you can fly?",
+            "",
+            "  # not a comment",
+            "# a comment",
+            "go ->",
+            "->",
+            "CLIFF",
+            "jump \\-> FLY",
+            "CLIFF 50% | FLY 50%",
+            "Continue...",
+            "[first] You're here.",
+            "[again]",
+            "* not a choice",
+        ])
+    }
+
+    fn choices(sections: usize) -> impl Strategy<Value = Vec<String>> {
+        let goto = 0..sections;
+        // either somewhere, or one of two places at random
+        let target = prop_oneof![
+            goto.clone().prop_map(|goto| NAMES[goto].to_owned()),
+            (goto.clone(), goto.clone(), 1..100u32).prop_map(|(heads, tails, chance)| format!(
+                "{} {}% | {} {}%",
+                NAMES[heads],
+                chance,
+                NAMES[tails],
+                100 - chance
+            )),
+        ];
+        let choice = (
+            prop::sample::select(vec![
+                "yes",
+                "no",
+                "  # hash",
+                "Continue...",
+                "maybe\\",
+                "* once",
+                "+ * sticky",
+                "*  # once",
+            ]),
+            target.clone(),
+        )
+            .prop_map(|(description, target)| format!("{} -> {}", description, target));
+
+        prop_oneof![
+            Just(vec!["-> END".to_owned()]),
+            target.prop_map(|target| vec![format!("-> {}", target)]),
+            prop::collection::vec(choice, 1..4),
+        ]
+    }
+
+    fn story() -> impl Strategy<Value = String> {
+        (1..=NAMES.len())
+            .prop_flat_map(|len| {
+                let section = (
+                    prop::collection::vec(description_line(), 1..6),
+                    choices(len),
+                );
+                (
+                    prop::option::of(prop::sample::select(vec!["The Cliff", "a: b", ""])),
+                    prop::option::of(0..len),
+                    prop::collection::vec(section, len),
+                )
+            })
+            .prop_map(|(title, start, sections)| {
+                let mut story = String::new();
+                if title.is_some() || start.is_some() {
+                    story.push_str("---\n");
+                    if let Some(title) = title {
+                        story.push_str(&format!("title: {}\n", title));
+                    }
+                    if let Some(start) = start {
+                        story.push_str(&format!("start: {}\n", NAMES[start]));
+                    }
+                    story.push_str("---\n");
+                }
+
+                for (i, (description, choices)) in sections.into_iter().enumerate() {
+                    story.push_str(&format!("{}:\n", NAMES[i]));
+                    for line in description
+                        .into_iter()
+                        .chain(choices.iter().map(String::as_str))
+                    {
+                        story.push_str(&format!("{}\n", line));
+                    }
+                    story.push_str("---\n");
+                }
+                story
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn write_then_parse(source in story()) {
+            let Ok(story) = Story::parse(&source) else {
+                return Err(TestCaseError::reject("not a valid story"));
+            };
+
+            let written = story.to_fater_string();
+            let parsed = Story::parse(&written).unwrap();
+            prop_assert_eq!(&parsed.clone().without_locations(), &story.clone().without_locations());
+            prop_assert_eq!(parsed.to_fater_string(), written);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert!(super::format("START:\n-> END\n---").is_err());
+        assert!(super::format("---\nfoo: bar\n---").is_err());
+    }
+}
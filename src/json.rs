@@ -0,0 +1,366 @@
+use std::{error::Error, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    builder::{SectionBuilder, StoryBuilder},
+    parse::{ParseError, Section, Story},
+};
+
+/// the version of the json representation, which changes whenever it does in
+/// a way that older readers couldn't cope with
+pub const VERSION: u32 = 1;
+
+// the representation itself, which is kept separate from the story's own
+// types so they can change without breaking it. it's in terms of what can be
+// written in a story, so endings are `-> END` rather than the choices they
+// turn into
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoryJson {
+    version: u32,
+    #[serde(default)]
+    metadata: MetadataJson,
+    sections: Vec<SectionJson>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MetadataJson {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SectionJson {
+    identifier: String,
+    paragraphs: Vec<String>,
+    choices: Vec<ChoiceJson>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChoiceJson {
+    /// empty for the `-> TARGET` shorthand
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    description: String,
+    /// `HEADS 50% | TAILS 50%` for a random choice
+    goto: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    once: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+// just enough to check the version before anything else
+#[derive(Deserialize)]
+struct Versioned {
+    version: u32,
+}
+
+impl Story {
+    /// the story as versioned json, which [`Story::from_json`] reads back
+    pub fn to_json(&self) -> String {
+        let metadata = self.metadata();
+        let story = StoryJson {
+            version: VERSION,
+            metadata: MetadataJson {
+                title: metadata.title().map(str::to_owned),
+                author: metadata.author().map(str::to_owned),
+                language: metadata.language().map(str::to_owned),
+                version: metadata.version().map(str::to_owned),
+                description: metadata.description().map(str::to_owned),
+                start: Some(metadata.start().to_string()),
+            },
+            sections: self.sections().values().map(SectionJson::new).collect(),
+        };
+
+        serde_json::to_string_pretty(&story).expect("stories are always valid json")
+    }
+
+    /// reads a story written by [`Story::to_json`], or generated some other
+    /// way, checking it just like [`Story::parse`] does
+    pub fn from_json(json: &str) -> Result<Story, JsonError> {
+        let Versioned { version } = serde_json::from_str(json).map_err(JsonError::Syntax)?;
+        if version != VERSION {
+            return Err(JsonError::UnsupportedVersion(version));
+        }
+        let story: StoryJson = serde_json::from_str(json).map_err(JsonError::Syntax)?;
+
+        let metadata = story.metadata;
+        let mut builder = StoryBuilder::new();
+        if let Some(title) = metadata.title {
+            builder = builder.title(title);
+        }
+        if let Some(author) = metadata.author {
+            builder = builder.author(author);
+        }
+        if let Some(language) = metadata.language {
+            builder = builder.language(language);
+        }
+        if let Some(version) = metadata.version {
+            builder = builder.version(version);
+        }
+        if let Some(description) = metadata.description {
+            builder = builder.description(description);
+        }
+        if let Some(start) = metadata.start {
+            builder = builder.start(start);
+        }
+
+        for section in story.sections {
+            builder = builder.section(section.into_builder());
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+impl SectionJson {
+    fn new(section: &Section) -> Self {
+        let choices = if section.is_ending() {
+            vec![ChoiceJson {
+                description: String::new(),
+                goto: "END".to_owned(),
+                once: false,
+            }]
+        } else {
+            section
+                .choices()
+                .iter()
+                .map(|choice| ChoiceJson {
+                    description: choice.description().to_string(),
+                    goto: choice.target(),
+                    once: choice.is_once(),
+                })
+                .collect()
+        };
+
+        Self {
+            identifier: section.identifier().to_string(),
+            paragraphs: section
+                .description()
+                .paragraphs()
+                .iter()
+                // with any `[first]` or `[again]`
+                .map(|paragraph| paragraph.to_string())
+                .collect(),
+            choices,
+        }
+    }
+
+    fn into_builder(self) -> SectionBuilder {
+        let mut builder = SectionBuilder::new(self.identifier);
+        for paragraph in self.paragraphs {
+            builder = builder.paragraph(paragraph);
+        }
+        for choice in self.choices {
+            builder = if choice.once {
+                builder.once(choice.description, choice.goto)
+            } else {
+                builder.choice(choice.description, choice.goto)
+            };
+        }
+        builder
+    }
+}
+
+#[derive(Debug)]
+pub enum JsonError {
+    /// it isn't json, or it's missing something, or has something it
+    /// shouldn't
+    Syntax(serde_json::Error),
+    UnsupportedVersion(u32),
+    /// it's fine as json, but not as a story
+    Invalid(ParseError),
+}
+
+impl From<ParseError> for JsonError {
+    fn from(err: ParseError) -> Self {
+        Self::Invalid(err)
+    }
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Syntax(err) => write!(f, "{}", err),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "version {} isn't supported, only version {}",
+                version, VERSION
+            ),
+            // there are no lines to point to, so go by the text
+            Self::Invalid(err) => write!(f, "{}: {}", err.text(), err.error_type()),
+        }
+    }
+}
+
+impl Error for JsonError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ParseErrorType;
+
+    const STORY: &str = "\
+---
+title: Café \"Deux\"
+author: Someone
+language: fr
+version: 2
+description: A story with a \\ in it
+start: CAFE
+---
+CAFE:
+« Un café, s'il vous plaît. »
+
+She says \"deux\" instead.
+order -> CH1::BILL
+wait -> CAFE
+---
+chapter CH1
+BILL:
+C'est 2 €.
+-> END
+---";
+
+    #[test]
+    fn round_trip() {
+        let story = Story::parse(STORY).unwrap();
+        let json = story.to_json();
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["version"], 1);
+        assert_eq!(
+            value["metadata"],
+            serde_json::json!({
+                "title": "Café \"Deux\"",
+                "author": "Someone",
+                "language": "fr",
+                "version": "2",
+                "description": "A story with a \\ in it",
+                "start": "CAFE"
+            })
+        );
+        assert_eq!(
+            value["sections"][0]["paragraphs"],
+            serde_json::json!([
+                "« Un café, s'il vous plaît. »",
+                "She says \"deux\" instead."
+            ])
+        );
+        assert_eq!(
+            value["sections"][0]["choices"][0],
+            serde_json::json!({ "description": "order", "goto": "CH1::BILL" })
+        );
+        // the shorthand, rather than what it's expanded to
+        assert_eq!(
+            value["sections"][1]["choices"],
+            serde_json::json!([{ "goto": "END" }])
+        );
+
+        let read = Story::from_json(&json).unwrap();
+        assert_eq!(read, story.without_locations());
+    }
+
+    #[test]
+    fn random() {
+        let story = Story::parse(
+            "START:\nHeads or tails?\nflip -> HEADS 50% | TAILS 50%\n---\nHEADS:\nHeads.\n-> END\n---\nTAILS:\nTails.\n-> END\n---",
+        )
+        .unwrap();
+        let json = story.to_json();
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value["sections"][0]["choices"],
+            serde_json::json!([{ "description": "flip", "goto": "HEADS 50% | TAILS 50%" }])
+        );
+        let read = Story::from_json(&json).unwrap();
+        assert!(read.sections().values().next().unwrap().choices()[0].is_random());
+    }
+
+    #[test]
+    fn visits() {
+        let story = Story::parse(
+            "START:\n[first] You see a door.\n\n[again] It's still shut.\n* open it -> ROOM\nwait -> START\n---\nROOM:\nIt's empty.\n-> END\n---",
+        )
+        .unwrap();
+        let json = story.to_json();
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        // written like in a story, and only saying it's once-only if it is
+        assert_eq!(
+            value["sections"][0]["paragraphs"],
+            serde_json::json!(["[first] You see a door.", "[again] It's still shut."])
+        );
+        assert_eq!(
+            value["sections"][0]["choices"],
+            serde_json::json!([
+                { "description": "open it", "goto": "ROOM", "once": true },
+                { "description": "wait", "goto": "START" }
+            ])
+        );
+        let read = Story::from_json(&json).unwrap();
+        let start = read.sections().values().next().unwrap();
+        assert_eq!(start.description(), story.sections()[0].description());
+        assert!(start.choices()[0].is_once());
+    }
+
+    #[test]
+    fn errors() {
+        let story = |sections: &str| {
+            Story::from_json(&format!("{{ \"version\": 1, \"sections\": {} }}", sections))
+        };
+
+        assert!(matches!(
+            Story::from_json("{ \"version\": 2, \"stories\": [] }"),
+            Err(JsonError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            story("[{ \"identifier\": \"START\", \"paragraphs\": [\"hi\"] }]"),
+            Err(JsonError::Syntax(_))
+        ));
+
+        let invalid = |result: Result<Story, JsonError>| match result {
+            Err(JsonError::Invalid(err)) => err.error_type().to_string(),
+            _ => panic!("expected it to be invalid"),
+        };
+        let dangling = story(
+            "[{ \"identifier\": \"START\", \"paragraphs\": [\"hi\"], \"choices\": [{ \"description\": \"go\", \"goto\": \"NOWHERE\" }] }]",
+        );
+        assert_eq!(invalid(dangling), ParseErrorType::DanglingGoto.to_string());
+        let reserved = story(
+            "[{ \"identifier\": \"END\", \"paragraphs\": [\"hi\"], \"choices\": [{ \"goto\": \"END\" }] }]",
+        );
+        assert_eq!(
+            invalid(reserved),
+            ParseErrorType::ReservedKeyUsage.to_string()
+        );
+        let section = "{ \"identifier\": \"START\", \"paragraphs\": [\"hi\"], \"choices\": [{ \"goto\": \"END\" }] }";
+        // it couldn't be written as a story, where it'd be another key
+        let title = Story::from_json(&format!(
+            "{{ \"version\": 1, \"metadata\": {{ \"title\": \"x\\nstart: NOPE\" }}, \"sections\": [{}] }}",
+            section
+        ));
+        assert_eq!(invalid(title), ParseErrorType::LineBreak.to_string());
+        assert!(matches!(
+            story(&format!("[{}, {}]", section, section)),
+            Err(JsonError::Invalid(ParseError { .. }))
+        ));
+    }
+}
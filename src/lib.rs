@@ -1,14 +1,30 @@
-use std::fs;
+#[cfg(not(target_arch = "wasm32"))]
+use std::process::ExitCode;
 
-use parse::Story;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::wasm_bindgen;
 
+pub mod analysis;
+pub mod builder;
 #[cfg(not(target_arch = "wasm32"))]
 mod cli;
-mod parse;
+pub mod diff;
+pub mod export;
+pub mod format;
+pub mod json;
+#[cfg(not(target_arch = "wasm32"))]
+mod lsp;
+pub mod parse;
+pub mod refactor;
+pub mod runtime;
+pub mod script;
+pub mod stats;
+pub mod symbols;
+pub mod syntax;
 #[cfg(not(target_arch = "wasm32"))]
 mod tui;
+pub mod twee;
+pub mod walkthrough;
 #[cfg(target_arch = "wasm32")]
 mod web;
 
@@ -16,10 +32,8 @@ mod web;
 // then egui renderer, and with web
 
 #[cfg(not(target_arch = "wasm32"))]
-pub fn run() {
-    let story = parse::load_file("iraq-2004.fater").unwrap().unwrap();
-
-    dbg!(story);
+pub fn run() -> ExitCode {
+    cli::run()
 }
 
 // entry point to web code
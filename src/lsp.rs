@@ -0,0 +1,815 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location,
+    MarkupContent, MarkupKind, OneOf, Position, PublishDiagnosticsParams, Range, ReferenceParams,
+    RenameParams, ServerCapabilities, SymbolKind, TextDocumentPositionParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url, WorkspaceEdit,
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+        PublishDiagnostics,
+    },
+    request::{
+        Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, References, Rename,
+        Request as _,
+    },
+};
+
+use crate::{
+    parse::{self, FileLoader, ParseErrorType, SectionIdentifier, Story},
+    symbols::{Definition, Symbols},
+    syntax::{LineKind, Span, SyntaxTree},
+};
+
+/// runs a language server over stdin and stdout, until the client shuts it
+/// down
+pub fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (connection, io_threads) = Connection::stdio();
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                }
+                connection
+                    .sender
+                    .send(Message::Response(server.request(request)))?;
+            }
+            Message::Notification(notification) => {
+                for notification in server.notification(notification) {
+                    connection
+                        .sender
+                        .send(Message::Notification(notification))?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+
+    // the writer thread only finishes once nothing can send to it
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![">".to_owned()]),
+            ..CompletionOptions::default()
+        }),
+        rename_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        ..ServerCapabilities::default()
+    }
+}
+
+// the documents the client has open, which might not be saved, keyed by path
+#[derive(Default)]
+struct Server {
+    documents: HashMap<PathBuf, String>,
+}
+
+// a story and every file it includes, with the story first
+struct Workspace {
+    files: Vec<(PathBuf, SyntaxTree)>,
+}
+
+// open documents win over what's on disk
+struct Loader<'a> {
+    documents: &'a HashMap<PathBuf, String>,
+}
+
+impl FileLoader for Loader<'_> {
+    fn load(&mut self, path: &Path) -> io::Result<String> {
+        match self.documents.get(path) {
+            Some(text) => Ok(text.clone()),
+            None => fs::read_to_string(path),
+        }
+    }
+}
+
+impl Server {
+    fn request(&mut self, request: Request) -> Response {
+        match request.method.as_str() {
+            GotoDefinition::METHOD => handle::<GotoDefinition>(request, |params| {
+                self.definition(&params.text_document_position_params)
+            }),
+            References::METHOD => handle::<References>(request, |params| self.references(params)),
+            Completion::METHOD => handle::<Completion>(request, |params| self.completion(params)),
+            Rename::METHOD => handle::<Rename>(request, |params| self.rename(params)),
+            DocumentSymbolRequest::METHOD => {
+                handle::<DocumentSymbolRequest>(request, |params| self.document_symbols(params))
+            }
+            HoverRequest::METHOD => handle::<HoverRequest>(request, |params| self.hover(params)),
+            _ => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unknown request {}", request.method),
+            ),
+        }
+    }
+
+    // keeps track of the open documents, returning the diagnostics to publish
+    fn notification(&mut self, notification: Notification) -> Vec<Notification> {
+        let mut closed = None;
+
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                if let Ok(params) = notification
+                    .extract::<lsp_types::DidOpenTextDocumentParams>(DidOpenTextDocument::METHOD)
+                    && let Ok(path) = params.text_document.uri.to_file_path()
+                {
+                    self.documents.insert(path, params.text_document.text);
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                if let Ok(params) = notification
+                    .extract::<lsp_types::DidChangeTextDocumentParams>(
+                        DidChangeTextDocument::METHOD,
+                    )
+                    && let Ok(path) = params.text_document.uri.to_file_path()
+                    // we only ask for whole documents
+                    && let Some(change) = params.content_changes.into_iter().last()
+                {
+                    self.documents.insert(path, change.text);
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                if let Ok(params) = notification
+                    .extract::<lsp_types::DidCloseTextDocumentParams>(DidCloseTextDocument::METHOD)
+                    && let Ok(path) = params.text_document.uri.to_file_path()
+                {
+                    self.documents.remove(&path);
+                    closed = Some(path);
+                }
+            }
+            _ => return Vec::new(),
+        }
+
+        // a change to one document can change the diagnostics of any other
+        // that's part of the same story, so just redo them all
+        let mut paths: Vec<PathBuf> = self.documents.keys().cloned().collect();
+        paths.sort();
+        let cleared = closed.map(|path| (path, Vec::new()));
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let diagnostics = self.diagnostics(&path);
+                (path, diagnostics)
+            })
+            .chain(cleared)
+            .filter_map(|(path, diagnostics)| {
+                let params = PublishDiagnosticsParams {
+                    uri: Url::from_file_path(path).ok()?,
+                    diagnostics,
+                    version: None,
+                };
+                Some(Notification::new(
+                    PublishDiagnostics::METHOD.to_owned(),
+                    params,
+                ))
+            })
+            .collect()
+    }
+
+    fn loader(&self) -> Loader<'_> {
+        Loader {
+            documents: &self.documents,
+        }
+    }
+
+    // the story and its includes, skipping anything that can't be loaded
+    fn workspace(&self, root: &Path) -> Workspace {
+        let mut files = Vec::new();
+        let mut seen = HashSet::new();
+        let mut queue = vec![root.to_path_buf()];
+
+        while let Some(path) = queue.pop() {
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+            let Ok(text) = self.loader().load(&path) else {
+                continue;
+            };
+
+            // backwards, so they're visited in order
+            queue.extend(parse::includes(&text, &path).into_iter().rev());
+            files.push((path, SyntaxTree::parse(&text)));
+        }
+
+        Workspace { files }
+    }
+
+    // the story a document is part of: another open document that includes
+    // it, or otherwise just the document itself
+    fn root(&self, path: &Path) -> PathBuf {
+        let mut others: Vec<&PathBuf> = self
+            .documents
+            .keys()
+            .filter(|other| *other != path)
+            .collect();
+        others.sort();
+
+        others
+            .into_iter()
+            .find(|other| self.workspace(other).tree(path).is_some())
+            .cloned()
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+
+    fn diagnostics(&self, path: &Path) -> Vec<Diagnostic> {
+        let root = self.root(path);
+        let workspace = self.workspace(&root);
+        let (Some(tree), Some(root_tree)) = (workspace.tree(path), workspace.tree(&root)) else {
+            return Vec::new();
+        };
+
+        let diagnostic = |range, severity, message| Diagnostic {
+            range,
+            severity: Some(severity),
+            source: Some("fater".to_owned()),
+            message,
+            ..Diagnostic::default()
+        };
+
+        let story = match Story::parse_with(root_tree.source(), Some(&root), &mut self.loader()) {
+            Ok(story) => story,
+            Err(err) if err.file() == Some(path) => {
+                // a choice going nowhere points at where it's going
+                let symbols = workspace.symbols();
+                let target = symbols
+                    .references()
+                    .iter()
+                    .filter(|_| matches!(err.error_type(), ParseErrorType::DanglingGoto))
+                    .find(|reference| {
+                        reference.file() == Some(path)
+                            && reference.line_num() == err.line_num()
+                            && symbols.definition(reference.identifier()).is_none()
+                    });
+                return vec![diagnostic(
+                    target.map_or_else(
+                        || line_range(tree.source(), err.line_num()),
+                        |reference| range(tree.source(), reference.span()),
+                    ),
+                    DiagnosticSeverity::ERROR,
+                    err.error_type().to_string(),
+                )];
+            }
+            // the error's in a file that isn't open, so the story gets it
+            Err(err) if root == path => {
+                return vec![diagnostic(
+                    line_range(tree.source(), 0),
+                    DiagnosticSeverity::ERROR,
+                    err.to_string(),
+                )];
+            }
+            Err(_) => return Vec::new(),
+        };
+
+        let symbols = workspace.symbols();
+        let analysis = story.analyze();
+        let start = story.metadata().start();
+
+        let unreachable = analysis
+            .unreachable()
+            .iter()
+            .map(|identifier| (identifier, format!("can't be reached from {}", start)));
+        let dead_ends = analysis
+            .dead_ends()
+            .iter()
+            .map(|identifier| (identifier, "can never reach an ending".to_owned()));
+        let loops = analysis.loops().iter().flat_map(|sections| {
+            let names: Vec<String> = sections.iter().map(ToString::to_string).collect();
+            sections.iter().map(move |identifier| {
+                (
+                    identifier,
+                    format!("is in a loop with no way out: {}", names.join(", ")),
+                )
+            })
+        });
+
+        let exhaustible = analysis.exhaustible().iter().map(|identifier| {
+            (
+                identifier,
+                "can run out of choices, since they're all once-only".to_owned(),
+            )
+        });
+
+        unreachable
+            .chain(dead_ends)
+            .chain(loops)
+            .chain(exhaustible)
+            .filter_map(|(identifier, message)| {
+                let definition = symbols
+                    .definition(identifier)
+                    .filter(|def| def.file() == Some(path))?;
+                Some(diagnostic(
+                    range(tree.source(), definition.span()),
+                    DiagnosticSeverity::WARNING,
+                    format!("{} {}", identifier, message),
+                ))
+            })
+            .collect()
+    }
+
+    // the story a position is in, and the section identifier at it, if any
+    fn at(
+        &self,
+        position: &TextDocumentPositionParams,
+    ) -> Result<(Workspace, Symbols, Option<SectionIdentifier>), String> {
+        let path = to_path(&position.text_document.uri)?;
+        let workspace = self.workspace(&self.root(&path));
+        let symbols = workspace.symbols();
+
+        let identifier = workspace.tree(&path).and_then(|tree| {
+            let offset = offset(tree.source(), position.position);
+            symbols.at(Some(&path), offset).cloned()
+        });
+        Ok((workspace, symbols, identifier))
+    }
+
+    fn definition(
+        &self,
+        position: &TextDocumentPositionParams,
+    ) -> Result<Option<GotoDefinitionResponse>, String> {
+        let (workspace, symbols, identifier) = self.at(position)?;
+
+        Ok(identifier
+            .and_then(|identifier| symbols.definition(&identifier))
+            .and_then(|def| workspace.location(def.file(), def.span()))
+            .map(GotoDefinitionResponse::Scalar))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>, String> {
+        let (workspace, symbols, identifier) = self.at(&params.text_document_position)?;
+        let Some(identifier) = identifier else {
+            return Ok(None);
+        };
+
+        let declaration = symbols
+            .definition(&identifier)
+            .filter(|_| params.context.include_declaration)
+            .map(|def| (def.file(), def.span()));
+        let references = symbols
+            .references_to(&identifier)
+            .map(|reference| (reference.file(), reference.span()));
+
+        Ok(Some(
+            declaration
+                .into_iter()
+                .chain(references)
+                .filter_map(|(file, span)| workspace.location(file, span))
+                .collect(),
+        ))
+    }
+
+    fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>, String> {
+        let position = &params.text_document_position;
+        let path = to_path(&position.text_document.uri)?;
+        let workspace = self.workspace(&self.root(&path));
+        let Some(tree) = workspace.tree(&path) else {
+            return Ok(None);
+        };
+
+        // only for the target of a choice
+        let offset = offset(tree.source(), position.position);
+        let line_start = tree.source()[..offset].rfind('\n').map_or(0, |i| i + 1);
+        if !tree.source()[line_start..offset].contains("->") {
+            return Ok(None);
+        }
+
+        let symbols = workspace.symbols();
+        let sections = symbols.definitions().iter().map(|def| CompletionItem {
+            label: def.identifier().to_string(),
+            kind: Some(CompletionItemKind::REFERENCE),
+            detail: workspace.description(def).lines().next().map(str::to_owned),
+            ..CompletionItem::default()
+        });
+        let end = CompletionItem {
+            label: "END".to_owned(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: Some("Restart from beginning, or return to menu".to_owned()),
+            ..CompletionItem::default()
+        };
+
+        Ok(Some(CompletionResponse::Array(
+            sections.chain([end]).collect(),
+        )))
+    }
+
+    fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>, String> {
+        let (workspace, symbols, identifier) = self.at(&params.text_document_position)?;
+        let Some(old) = identifier else {
+            return Ok(None);
+        };
+
+        let mut new = SectionIdentifier::parse((0, &params.new_name), false)
+            .map_err(|err| err.error_type().to_string())?;
+        // the name as it's written is usually without the chapter
+        if let Some(chapter) = old.chapter()
+            && new.chapter().is_none()
+        {
+            new = new.in_chapter(chapter);
+        }
+
+        let edits = symbols.rename(&old, &new).map_err(|err| err.to_string())?;
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+        for edit in edits {
+            let Some(location) = workspace.location(edit.file.as_deref(), edit.span) else {
+                continue;
+            };
+            changes.entry(location.uri).or_default().push(TextEdit {
+                range: location.range,
+                new_text: edit.text,
+            });
+        }
+
+        Ok(Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..WorkspaceEdit::default()
+        }))
+    }
+
+    fn document_symbols(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>, String> {
+        let path = to_path(&params.text_document.uri)?;
+        let workspace = self.workspace(&self.root(&path));
+        let Some(tree) = workspace.tree(&path) else {
+            return Ok(None);
+        };
+
+        let symbols = workspace.symbols();
+        #[allow(deprecated)]
+        let sections = symbols
+            .definitions()
+            .iter()
+            .filter(|def| def.file() == Some(&path))
+            .map(|def| DocumentSymbol {
+                name: def.identifier().to_string(),
+                detail: workspace.description(def).lines().next().map(str::to_owned),
+                kind: SymbolKind::OBJECT,
+                tags: None,
+                deprecated: None,
+                range: range(tree.source(), def.section_span()),
+                selection_range: range(tree.source(), def.span()),
+                children: None,
+            })
+            .collect();
+
+        Ok(Some(DocumentSymbolResponse::Nested(sections)))
+    }
+
+    fn hover(&self, params: HoverParams) -> Result<Option<Hover>, String> {
+        let (workspace, symbols, identifier) = self.at(&params.text_document_position_params)?;
+
+        Ok(identifier
+            .and_then(|identifier| symbols.definition(&identifier))
+            .map(|def| Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: format!("**{}**\n\n{}", def.identifier(), workspace.description(def)),
+                }),
+                range: None,
+            }))
+    }
+}
+
+impl Workspace {
+    fn tree(&self, path: &Path) -> Option<&SyntaxTree> {
+        self.files
+            .iter()
+            .find(|(file, _)| file == path)
+            .map(|(_, tree)| tree)
+    }
+
+    fn symbols(&self) -> Symbols {
+        Symbols::new(
+            self.files
+                .iter()
+                .map(|(path, tree)| (Some(path.as_path()), tree)),
+        )
+    }
+
+    fn location(&self, file: Option<&Path>, span: Span) -> Option<Location> {
+        let file = file?;
+        Some(Location {
+            uri: Url::from_file_path(file).ok()?,
+            range: range(self.tree(file)?.source(), span),
+        })
+    }
+
+    // the description of a section as it's written, which works even if the
+    // section doesn't parse
+    fn description(&self, definition: &Definition) -> String {
+        let Some(tree) = definition.file().and_then(|file| self.tree(file)) else {
+            return String::new();
+        };
+        let Some(node) = tree
+            .nodes()
+            .iter()
+            .find(|node| node.span() == definition.section_span())
+        else {
+            return String::new();
+        };
+
+        let lines: Vec<&str> = node
+            .lines()
+            .iter()
+            .skip(1)
+            .take_while(|line| line.kind() != LineKind::Choice)
+            .filter(|line| matches!(line.kind(), LineKind::Description | LineKind::Blank))
+            .map(|line| tree.line_text(line).trim())
+            .collect();
+        lines.join("\n").trim().to_owned()
+    }
+}
+
+// deserializes the params of a request, and serializes whatever the handler
+// makes of them
+fn handle<R: lsp_types::request::Request>(
+    request: Request,
+    handler: impl FnOnce(R::Params) -> Result<R::Result, String>,
+) -> Response {
+    let params = match serde_json::from_value::<R::Params>(request.params) {
+        Ok(params) => params,
+        Err(err) => {
+            return Response::new_err(request.id, ErrorCode::InvalidParams as i32, err.to_string());
+        }
+    };
+
+    match handler(params) {
+        Ok(result) => Response::new_ok(request.id, result),
+        Err(message) => Response::new_err(request.id, ErrorCode::RequestFailed as i32, message),
+    }
+}
+
+fn to_path(uri: &Url) -> Result<PathBuf, String> {
+    uri.to_file_path()
+        .map_err(|()| format!("{} isn't a file", uri))
+}
+
+// positions count lines from 0, and characters in utf-16
+fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    Position::new(
+        before.matches('\n').count() as u32,
+        before[line_start..].encode_utf16().count() as u32,
+    )
+}
+
+fn offset(text: &str, position: Position) -> usize {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum();
+    let line = text[line_start..].split('\n').next().unwrap_or("");
+
+    let mut characters = 0;
+    for (i, ch) in line.char_indices() {
+        if characters >= position.character as usize {
+            return line_start + i;
+        }
+        characters += ch.len_utf16();
+    }
+    line_start + line.len()
+}
+
+fn range(text: &str, span: Span) -> Range {
+    Range::new(position(text, span.start), position(text, span.end))
+}
+
+// the whole of a line, without its line ending
+fn line_range(text: &str, line_num: usize) -> Range {
+    let line = text.lines().nth(line_num).unwrap_or("");
+    Range::new(
+        Position::new(line_num as u32, 0),
+        Position::new(line_num as u32, line.encode_utf16().count() as u32),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_server::RequestId;
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    const MAIN: &str = "\
+START:
+Believe you can fly?
+yes -> CH1::FLY
+no -> GROUND
+---
+GROUND:
+You stay on the ground.
+-> END
+---
+include \"cliff.fater\"
+";
+
+    const CLIFF: &str = "\
+chapter CH1
+FLY:
+You can't.
+again -> FLY
+out -> START
+---
+";
+
+    fn server() -> Server {
+        let mut server = Server::default();
+        server
+            .documents
+            .insert(PathBuf::from("/story/main.fater"), MAIN.to_owned());
+        server
+            .documents
+            .insert(PathBuf::from("/story/cliff.fater"), CLIFF.to_owned());
+        server
+    }
+
+    fn request(server: &mut Server, method: &str, params: Value) -> Value {
+        let response = server.request(Request::new(RequestId::from(0), method.to_owned(), params));
+        assert!(response.error.is_none(), "{:?}", response.error);
+        response.result.unwrap()
+    }
+
+    fn at(file: &str, line: u32, character: u32) -> Value {
+        json!({
+            "textDocument": { "uri": format!("file:///story/{}", file) },
+            "position": { "line": line, "character": character },
+        })
+    }
+
+    #[test]
+    fn navigation() {
+        let mut server = server();
+
+        // from the choice in main to the section in the included file
+        assert_eq!(
+            request(&mut server, GotoDefinition::METHOD, at("main.fater", 2, 13)),
+            json!({
+                "uri": "file:///story/cliff.fater",
+                "range": {
+                    "start": { "line": 1, "character": 0 },
+                    "end": { "line": 1, "character": 3 },
+                },
+            })
+        );
+
+        let mut params = at("cliff.fater", 1, 1);
+        params["context"] = json!({ "includeDeclaration": false });
+        let references = request(&mut server, References::METHOD, params);
+        let lines: Vec<(&str, u64)> = references
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|location| {
+                (
+                    location["uri"].as_str().unwrap(),
+                    location["range"]["start"]["line"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("file:///story/main.fater", 2),
+                ("file:///story/cliff.fater", 3),
+            ]
+        );
+
+        let hover = request(&mut server, HoverRequest::METHOD, at("cliff.fater", 4, 9));
+        assert_eq!(
+            hover["contents"]["value"],
+            "**START**\n\nBelieve you can fly?"
+        );
+
+        let symbols = request(
+            &mut server,
+            DocumentSymbolRequest::METHOD,
+            json!({ "textDocument": { "uri": "file:///story/cliff.fater" } }),
+        );
+        assert_eq!(symbols[0]["name"], "CH1::FLY");
+        assert_eq!(symbols[0]["detail"], "You can't.");
+    }
+
+    #[test]
+    fn completion() {
+        let mut server = server();
+
+        let completions = request(&mut server, Completion::METHOD, at("main.fater", 3, 6));
+        let labels: Vec<&str> = completions
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(labels, vec!["START", "GROUND", "CH1::FLY", "END"]);
+
+        // not in a description
+        assert_eq!(
+            request(&mut server, Completion::METHOD, at("main.fater", 1, 3)),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn rename() {
+        let mut server = server();
+
+        let mut params = at("cliff.fater", 3, 10);
+        params["newName"] = json!("JUMP");
+        let edit = request(&mut server, Rename::METHOD, params);
+
+        let edits = |file: &str| -> Vec<(u64, String)> {
+            edit["changes"][format!("file:///story/{}", file)]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|edit| {
+                    (
+                        edit["range"]["start"]["line"].as_u64().unwrap(),
+                        edit["newText"].as_str().unwrap().to_owned(),
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(edits("main.fater"), vec![(2, "CH1::JUMP".to_owned())]);
+        assert_eq!(
+            edits("cliff.fater"),
+            vec![(1, "JUMP".to_owned()), (3, "JUMP".to_owned())]
+        );
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut server = server();
+        server.documents.insert(
+            PathBuf::from("/story/cliff.fater"),
+            CLIFF.replace("-> FLY", "-> NOWHERE"),
+        );
+
+        let diagnostics = server.diagnostics(Path::new("/story/cliff.fater"));
+        assert_eq!(diagnostics.len(), 1);
+        // just the section it goes to
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(3, 9), Position::new(3, 16))
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+
+        // the included file's fine on its own, since the story it's in has
+        // START
+        server
+            .documents
+            .insert(PathBuf::from("/story/cliff.fater"), CLIFF.to_owned());
+        assert!(
+            server
+                .diagnostics(Path::new("/story/cliff.fater"))
+                .is_empty()
+        );
+
+        // a header that's just been typed at the end of the file
+        server.documents.insert(
+            PathBuf::from("/story/cliff.fater"),
+            format!("{}LAMP:", CLIFF),
+        );
+        let diagnostics = server.diagnostics(Path::new("/story/cliff.fater"));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start.line, 6);
+        assert_eq!(
+            diagnostics[0].message,
+            ParseErrorType::ExpectedDescription.to_string()
+        );
+    }
+
+    #[test]
+    fn positions() {
+        let text = "ab\nçd€e\n";
+        assert_eq!(position(text, 9), Position::new(1, 3));
+        assert_eq!(offset(text, Position::new(1, 3)), 9);
+        assert_eq!(offset(text, Position::new(1, 100)), 10);
+    }
+}
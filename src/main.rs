@@ -1,3 +1,5 @@
-fn main() {
-    fater::run();
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    fater::run()
 }
@@ -1,21 +1,164 @@
 use std::{
-    collections::HashMap, error::Error, fmt::Display, fs, io, num::TryFromIntError, path::Path,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    fs, io,
+    iter::Peekable,
+    path::{Component, Path, PathBuf},
 };
 
-use wasm_bindgen::prelude::wasm_bindgen;
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::syntax::{NodeKind, SyntaxTree};
 
 pub fn load_file(path: impl AsRef<Path>) -> io::Result<ParseResult<Story>> {
-    Ok(Story::parse(&fs::read_to_string(path)?))
+    load_file_with(path, &mut DiskLoader)
+}
+
+/// like [`load_file`], but reading the story and anything it includes
+/// through `loader`, rather than directly from disk
+pub fn load_file_with(
+    path: impl AsRef<Path>,
+    loader: &mut impl FileLoader,
+) -> io::Result<ParseResult<Story>> {
+    let path = normalize(path.as_ref());
+    let story = loader.load(&path)?;
+
+    Ok(Story::parse_with(&story, Some(&path), loader))
 }
 
 pub fn load_str(str: &str) -> ParseResult<Story> {
     Story::parse(str)
 }
 
+/// where the text of a story's files comes from --- the disk natively, but
+/// the web build has to fetch them ahead of time, and supply them from memory
+pub trait FileLoader {
+    fn load(&mut self, path: &Path) -> io::Result<String>;
+}
+
+pub struct DiskLoader;
+
+impl FileLoader for DiskLoader {
+    fn load(&mut self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MemoryLoader {
+    files: HashMap<PathBuf, String>,
+}
+
+impl MemoryLoader {
+    pub fn insert(&mut self, path: impl AsRef<Path>, text: String) {
+        self.files.insert(normalize(path.as_ref()), text);
+    }
+
+    pub fn contains(&self, path: impl AsRef<Path>) -> bool {
+        self.files.contains_key(&normalize(path.as_ref()))
+    }
+}
+
+impl FileLoader for MemoryLoader {
+    fn load(&mut self, path: &Path) -> io::Result<String> {
+        self.files
+            .get(&normalize(path))
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "file not loaded"))
+    }
+}
+
+/// the paths of every file directly included by `story`, resolved relative to
+/// `path`, so they can be fetched before parsing
+pub fn includes(story: &str, path: &Path) -> Vec<PathBuf> {
+    lines(story)
+        .filter_map(|(num, line)| parse_include(num, line))
+        .filter_map(Result::ok)
+        .map(|include| include_path(path, &include))
+        .collect()
+}
+
+fn include_path(including: &Path, include: &str) -> PathBuf {
+    normalize(&including.parent().unwrap_or(Path::new("")).join(include))
+}
+
+// we can't canonicalize, since the file might not be on disk, so just clean
+// the path up lexically, which is enough to catch cycles
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// `include "file.fater"`, returning None if the line isn't an include at all
+pub(crate) fn parse_include(line_num: usize, line: &str) -> Option<ParseResult<String>> {
+    let rest = line.trim().strip_prefix("include")?;
+
+    // so that an include has to be followed by whitespace
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    Some(
+        rest.trim()
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .filter(|path| !path.is_empty() && !path.contains('"'))
+            .map(str::to_owned)
+            .ok_or_else(|| {
+                ParseError::new(ParseErrorType::InvalidInclude, line_num, line.to_owned())
+            }),
+    )
+}
+
+// `chapter NAME`, which puts every section after it in the file into that
+// chapter. returns None if the line isn't a chapter at all
+pub(crate) fn parse_chapter(line_num: usize, line: &str) -> Option<ParseResult<SectionIdentifier>> {
+    let rest = line.trim().strip_prefix("chapter")?;
+
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    Some(
+        SectionIdentifier::parse((line_num, rest), false).and_then(|identifier| {
+            if identifier.chapter().is_some() || identifier.is_reserved() {
+                Err(ParseError::new(
+                    ParseErrorType::InvalidChapter,
+                    line_num,
+                    line.to_owned(),
+                ))
+            } else {
+                Ok(identifier)
+            }
+        }),
+    )
+}
+
+// the lines of a file with their numbers, without comments
+fn lines(str: &str) -> Peekable<impl Iterator<Item = (usize, &str)>> {
+    // TODO: trim before the lines iter?
+    str.lines()
+        .enumerate()
+        .filter(|(_, str)| !str.starts_with('#'))
+        .peekable()
+}
+
 // #[wasm_bindgen]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Story {
-    sections: HashMap<SectionIdentifier, Section>,
+    metadata: Metadata,
+    sections: IndexMap<SectionIdentifier, Section>,
 }
 
 // #[cfg(target_arch = "wasm32")]
@@ -27,63 +170,397 @@ pub struct Story {
 
 impl Story {
     pub fn parse(story: &str) -> ParseResult<Story> {
-        // TODO: attempt to, in a loop, parse as a section --- error if failure
+        // there's no file to resolve includes relative to, so any include
+        // will fail to load
+        Self::parse_with(story, None, &mut MemoryLoader::default())
+    }
 
-        let mut sections: HashMap<SectionIdentifier, Section> = HashMap::new();
+    /// parses a story that can `include` other files, which are resolved
+    /// relative to `path`, and read through `loader`
+    pub fn parse_with(
+        story: &str,
+        path: Option<&Path>,
+        loader: &mut impl FileLoader,
+    ) -> ParseResult<Story> {
+        Self::from_syntax(&SyntaxTree::parse(story), path, loader)
+    }
 
-        // TODO: trim before the lines iter?
-        let mut iter = story
-            .lines()
-            .enumerate()
-            .filter(|(_, str)| !str.starts_with('#'))
-            .peekable();
-
-        loop {
-            if iter.peek().is_none() {
-                // we're out of lines --- running out in the middle of a section is
-                // an error, but here, it just means there aren't any more sections
-                break;
-            } else if iter.peek().expect("not none, as above").1.is_empty() {
-                iter.next();
+    /// derives a story from its syntax tree, the same way as
+    /// [`Story::parse_with`]
+    pub fn from_syntax(
+        tree: &SyntaxTree,
+        path: Option<&Path>,
+        loader: &mut impl FileLoader,
+    ) -> ParseResult<Story> {
+        let mut sections: IndexMap<SectionIdentifier, Section> = IndexMap::new();
+
+        let metadata = match tree
+            .nodes()
+            .iter()
+            .find(|node| node.kind() == NodeKind::Metadata)
+        {
+            Some(node) => Metadata::parse(&mut tree.parser_lines(node).peekable()),
+            None => Ok(Metadata::default()),
+        }
+        .map_err(|err| err.in_file(path))?;
+
+        let mut stack = path.map(normalize).into_iter().collect();
+        Self::parse_sections(tree, path, loader, &mut stack, &mut sections)?;
+
+        Self::link(metadata, sections, path)
+    }
+
+    // builds a story out of sections that have already been checked, with
+    // the same checks across them as when parsing. there's no source, so
+    // errors are on whatever line the section was given, with the section
+    // identifier as their text
+    pub(crate) fn from_parts(metadata: Metadata, parts: Vec<Section>) -> ParseResult<Story> {
+        let mut sections: IndexMap<SectionIdentifier, Section> = IndexMap::new();
+
+        for section in parts {
+            if let Some(dup_section) = sections.get(&section.identifier) {
+                let (line_num, text) = (section.line_num, section.identifier.to_string());
+                return Err(ParseError::new(
+                    ParseErrorType::DuplicateSections(
+                        Box::new(section),
+                        Box::new(dup_section.clone()),
+                    ),
+                    line_num,
+                    text,
+                ));
+            }
+            sections.insert(section.identifier.clone(), section);
+        }
+
+        Self::link(metadata, sections, None)
+    }
+
+    // the checks that need every section: that gotos go somewhere, and that
+    // there's a start
+    fn link(
+        metadata: Metadata,
+        mut sections: IndexMap<SectionIdentifier, Section>,
+        path: Option<&Path>,
+    ) -> ParseResult<Story> {
+        // gotos can be relative to the chapter they're in, which we can only
+        // resolve now that we know every section
+        let identifiers: HashSet<SectionIdentifier> = sections.keys().cloned().collect();
+        for section in sections.values_mut() {
+            let chapter = section.identifier.chapter();
+            for choice in &mut section.choices {
+                if choice.is_special() {
+                    continue;
+                }
+
+                let text = format!("{} -> {}", choice.description, choice.target());
+                for outcome in &mut choice.outcomes {
+                    outcome.goto = outcome
+                        .goto
+                        .resolve(chapter, |identifier| identifiers.contains(identifier))
+                        .ok_or_else(|| {
+                            ParseError::new(
+                                ParseErrorType::DanglingGoto,
+                                choice.line_num,
+                                text.clone(),
+                            )
+                            .in_file(section.file.as_deref())
+                        })?;
+                }
+            }
+        }
+
+        if !sections.contains_key(&metadata.start) {
+            return Err(ParseError::new(
+                ParseErrorType::MissingStart,
+                metadata.line_num,
+                metadata.start.to_string(),
+            )
+            .in_file(path));
+        }
+
+        Ok(Self { metadata, sections })
+    }
+
+    // parses every section of a single file into `sections`, recursing into
+    // includes. `stack` is the chain of files currently being included, so we
+    // can catch cycles
+    fn parse_sections(
+        tree: &SyntaxTree,
+        file: Option<&Path>,
+        loader: &mut impl FileLoader,
+        stack: &mut Vec<PathBuf>,
+        sections: &mut IndexMap<SectionIdentifier, Section>,
+    ) -> ParseResult<()> {
+        // set by `chapter NAME`, for the rest of the file
+        let mut chapter: Option<SectionIdentifier> = None;
+
+        for node in tree.nodes() {
+            let Some((line_num, text)) = tree.parser_lines(node).next() else {
                 continue;
+            };
+
+            match node.kind() {
+                NodeKind::Trivia | NodeKind::Metadata => {}
+                NodeKind::Include => {
+                    let include = parse_include(line_num, text)
+                        .expect("the tree only has valid include lines")
+                        .map_err(|err| err.in_file(file))?;
+
+                    let path = match file {
+                        Some(file) => include_path(file, &include),
+                        None => normalize(Path::new(&include)),
+                    };
+                    let error = |error_type| {
+                        ParseError::new(error_type, line_num, text.to_owned()).in_file(file)
+                    };
+
+                    if stack.contains(&path) {
+                        return Err(error(ParseErrorType::IncludeCycle(path)));
+                    }
+                    let included = loader
+                        .load(&path)
+                        .map_err(|err| error(ParseErrorType::IncludeFailed(path.clone(), err)))?;
+
+                    stack.push(path.clone());
+                    let included = SyntaxTree::parse(&included);
+                    Self::parse_sections(&included, Some(&path), loader, stack, sections)?;
+                    stack.pop();
+                }
+                NodeKind::Chapter => {
+                    let identifier = parse_chapter(line_num, text)
+                        .expect("the tree only has valid chapter lines");
+                    chapter = Some(identifier.map_err(|err| err.in_file(file))?);
+                }
+                NodeKind::Section => {
+                    let mut section =
+                        Section::parse(tree.parser_lines(node)).map_err(|err| err.in_file(file))?;
+                    section.file = file.map(Path::to_path_buf);
+                    if let Some(chapter) = &chapter
+                        && section.identifier.chapter().is_none()
+                    {
+                        section.identifier = section.identifier.in_chapter(chapter.name());
+                    }
+
+                    if let Some(dup_section) = sections.get(&section.identifier) {
+                        return Err(ParseError::new(
+                            ParseErrorType::DuplicateSections(
+                                Box::new(section),
+                                Box::new(dup_section.clone()),
+                            ),
+                            line_num,
+                            text.to_owned(),
+                        )
+                        .in_file(file));
+                    }
+                    sections
+                        .insert(section.identifier.clone(), section)
+                        // we want to panic if Some gets returned
+                        .map_or(Ok(()), |_| Err(()))
+                        .expect("verified above");
+                }
             }
+        }
 
-            let (line_num, text) = iter.peek().expect("validated above").to_owned();
-            let section = Section::parse(&mut iter)?;
+        Ok(())
+    }
 
-            if let Some(dup_section) = sections.get(&section.identifier) {
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// every section, in the order they were written, with the sections of an
+    /// included file where it was included
+    pub fn sections(&self) -> &IndexMap<SectionIdentifier, Section> {
+        &self.sections
+    }
+}
+
+#[cfg(test)]
+impl Story {
+    // the story, forgetting where anything in it was written, so stories made
+    // in different ways can be compared
+    pub(crate) fn without_locations(mut self) -> Self {
+        self.metadata.line_num = 0;
+        for section in self.sections.values_mut() {
+            section.line_num = 0;
+            section.file = None;
+            for choice in &mut section.choices {
+                choice.line_num = 0;
+            }
+        }
+        self
+    }
+}
+
+/// the optional front-matter block at the top of a story, fenced by `---`
+/// lines, with one `key: value` pair per line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    title: Option<String>,
+    author: Option<String>,
+    language: Option<String>,
+    version: Option<String>,
+    description: Option<String>,
+    start: SectionIdentifier,
+    // where the start section was declared, or the first line if it wasn't,
+    // for reporting a missing start section
+    line_num: usize,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self {
+            title: None,
+            author: None,
+            language: None,
+            version: None,
+            description: None,
+            start: SectionIdentifier("START".to_owned()),
+            line_num: 0,
+        }
+    }
+}
+
+impl Metadata {
+    // metadata that didn't come from a header, with the same check on the
+    // start section. anything left as None keeps its default, and the line is
+    // only for errors
+    pub(crate) fn new(
+        title: Option<String>,
+        author: Option<String>,
+        language: Option<String>,
+        version: Option<String>,
+        description: Option<String>,
+        start: Option<SectionIdentifier>,
+        line_num: usize,
+    ) -> ParseResult<Self> {
+        let mut metadata = Self {
+            title,
+            author,
+            language,
+            version,
+            description,
+            line_num,
+            ..Self::default()
+        };
+
+        if let Some(start) = start {
+            if start.is_reserved() {
                 return Err(ParseError::new(
-                    ParseErrorType::DuplicateSections(section, dup_section.clone()),
+                    ParseErrorType::ReservedKeyUsage,
                     line_num,
-                    text.to_owned(),
+                    start.to_string(),
                 ));
             }
-            sections
-                .insert(section.identifier.clone(), section)
-                // we want to panic if Some gets returned
-                .map_or(Ok(()), |_| Err(()))
-                .expect("verified above");
-        }
-
-        for section in sections.values() {
-            for choice in &section.choices {
-                if !matches!(choice.goto.0.as_str(), "__RESTART" | "__MENU")
-                    && !sections.contains_key(&choice.goto)
-                {
+            metadata.start = start;
+        }
+
+        Ok(metadata)
+    }
+
+    pub(crate) fn parse<'a, I>(iter: &mut std::iter::Peekable<I>) -> ParseResult<Self>
+    where
+        I: Iterator<Item = (usize, &'a str)>,
+    {
+        let mut metadata = Self::default();
+
+        // skip leading blank lines, so we can tell whether there's a header
+        while iter.next_if(|(_, line)| line.trim().is_empty()).is_some() {}
+
+        let Some((num, _)) = iter.next_if(|(_, line)| line.trim() == "---") else {
+            // no header, everything is left at the defaults
+            return Ok(metadata);
+        };
+        metadata.line_num = num;
+
+        let mut seen = Vec::new();
+        for (num, line) in iter.by_ref() {
+            let line = line.trim();
+
+            if line == "---" {
+                return Ok(metadata);
+            } else if line.is_empty() {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once(':') else {
+                return Err(ParseError::new(
+                    ParseErrorType::MissingColon,
+                    num,
+                    line.to_owned(),
+                ));
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            if seen.contains(&key) {
+                return Err(ParseError::new(
+                    ParseErrorType::DuplicateMetadataKey(key.to_owned()),
+                    num,
+                    line.to_owned(),
+                ));
+            }
+            seen.push(key);
+
+            let value_owned = Some(value.to_owned());
+            match key {
+                "title" => metadata.title = value_owned,
+                "author" => metadata.author = value_owned,
+                "language" => metadata.language = value_owned,
+                "version" => metadata.version = value_owned,
+                "description" => metadata.description = value_owned,
+                "start" => {
+                    metadata.start = SectionIdentifier::parse((num, value), false)?;
+                    metadata.line_num = num;
+
+                    if metadata.start.is_reserved() {
+                        return Err(ParseError::new(
+                            ParseErrorType::ReservedKeyUsage,
+                            num,
+                            line.to_owned(),
+                        ));
+                    }
+                }
+                _ => {
                     return Err(ParseError::new(
-                        ParseErrorType::DanglingGoto,
-                        section.line_num,
-                        format!("{} -> {}", choice.description, choice.goto),
+                        ParseErrorType::UnknownMetadataKey(key.to_owned()),
+                        num,
+                        line.to_owned(),
                     ));
                 }
             }
         }
 
-        Ok(Self { sections })
+        // we ran out of lines without finding the closing separator
+        Err(ParseError::new(
+            ParseErrorType::UnterminatedMetadata,
+            metadata.line_num,
+            "---".to_owned(),
+        ))
     }
 
-    pub fn sections(&self) -> &HashMap<SectionIdentifier, Section> {
-        &self.sections
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// the section the story begins at, `START` unless overridden
+    pub fn start(&self) -> &SectionIdentifier {
+        &self.start
     }
 }
 
@@ -92,23 +569,23 @@ pub struct Section {
     identifier: SectionIdentifier,
     description: Description,
     choices: Vec<Choice>,
-    // TODO: how to avoid this being necessary when when validating?
-    // this hoists, obviously, so we can't just validate as we parse
+    // the line of its `NAME:` header, for pointing at it
     line_num: usize,
+    // the file the section came from, if it was loaded from one
+    file: Option<PathBuf>,
 }
 
 impl Section {
-    fn parse<'a>(mut iter: impl Iterator<Item = (usize, &'a str)>) -> ParseResult<Self> {
+    pub(crate) fn parse<'a>(mut iter: impl Iterator<Item = (usize, &'a str)>) -> ParseResult<Self> {
         // let section_identifier = { for line in iter {
         //     if line
         // } };
 
-        let mut section_identifier =
-            SectionIdentifier::parse(iter.next().expect("required to be Some"), true)?;
+        let header = iter.next().expect("required to be Some");
+        let section_identifier = SectionIdentifier::parse(header, true)?;
 
-        let mut section_description = String::new();
+        let mut section_description = Vec::new();
         let mut reached_choices = false;
-        let mut finished = false;
         let mut choices = Vec::new();
         let mut line_num = None;
         let mut current_line = None;
@@ -132,7 +609,6 @@ impl Section {
 
             if line == "---" {
                 if reached_choices {
-                    finished = true;
                     break;
                 } else {
                     return Err(ParseError::new(
@@ -149,7 +625,7 @@ impl Section {
                 Ok(choice) => choice,
                 Err(err) => {
                     if !reached_choices {
-                        section_description.push_str(&format!("{}\n", line));
+                        section_description.push((num, line));
                         continue;
                     } else {
                         // return Err(ParseError::new(ParseErrorType::ExpectedChoice, line_num));
@@ -175,79 +651,178 @@ impl Section {
 
         // TODO: refactor line and line_num
 
-        // if we end without finding any description, that's an error, and
-        // idem for choices
-        if section_description.is_empty() {
+        // a header on the last line, which happens while one's being typed
+        let (Some(line_num), Some(current_line)) = (line_num, current_line) else {
             return Err(ParseError::new(
                 ParseErrorType::ExpectedDescription,
-                line_num.unwrap(),
-                current_line.unwrap().to_owned(),
+                header.0,
+                header.1.to_owned(),
             ));
+        };
+        let mut section = Self::new(
+            section_identifier,
+            &section_description,
+            choices,
+            line_num,
+            current_line,
+        )?;
+        section.line_num = header.0;
+        Ok(section)
+    }
+
+    // the checks every section has to pass, however it was made, and the
+    // expansion of the choice shorthands. the description is its lines, with
+    // the line each is on, and errors in it or the choices are on those
+    // lines. the line is where other errors are, and where the section is,
+    // unless it's given another afterwards
+    pub(crate) fn new(
+        identifier: SectionIdentifier,
+        description: &[(usize, &str)],
+        mut choices: Vec<Choice>,
+        line_num: usize,
+        line: &str,
+    ) -> ParseResult<Self> {
+        let error = |error_type| ParseError::new(error_type, line_num, line.to_owned());
+        let choice_error = |error_type, choice: &Choice| {
+            ParseError::new(error_type, choice.line_num, choice.description.to_string())
+        };
+
+        // if we end without finding any description, that's an error, and
+        // idem for choices
+        if description.is_empty() {
+            return Err(error(ParseErrorType::ExpectedDescription));
         } else if choices.is_empty() {
-            return Err(ParseError::new(
-                ParseErrorType::ExpectedChoice,
-                line_num.unwrap(),
-                current_line.unwrap().to_owned(),
-            ));
+            return Err(error(ParseErrorType::ExpectedChoice));
         }
 
-        let section_description = Description::new(&section_description);
+        if identifier.is_reserved() {
+            return Err(error(ParseErrorType::ReservedKeyUsage));
+        }
 
-        let mut section = Self {
-            identifier: section_identifier,
-            description: section_description,
-            choices,
-            line_num: line_num.unwrap(),
+        // sections that weren't parsed could have text that can't be written
+        // back out, as a separator that's a paragraph of its own, or a choice
+        // going two places at once
+        let blank = |i: Option<usize>| {
+            i.and_then(|i| description.get(i))
+                .is_none_or(|(_, line)| line.trim().is_empty())
         };
-
-        if let "END" | "__RESTART" | "__MENU" = section.identifier.0.as_str() {
+        if let Some((_, &(num, text))) = description.iter().enumerate().find(|(i, (_, text))| {
+            text.trim() == "---" && blank(i.checked_sub(1)) && blank(Some(i + 1))
+        }) {
             return Err(ParseError::new(
-                ParseErrorType::ReservedKeyUsage,
-                line_num.unwrap(),
-                current_line.unwrap().to_owned(),
+                ParseErrorType::UnexpectedSeparator,
+                num,
+                text.to_owned(),
             ));
         }
+        if let Some(choice) = choices
+            .iter()
+            .find(|choice| choice.description.to_string().contains("->"))
+        {
+            return Err(choice_error(ParseErrorType::MultipleArrows, choice));
+        }
+        // a choice is there or it isn't, on every visit
+        if let Some(choice) = choices.iter().find(|choice| {
+            choice
+                .description
+                .0
+                .iter()
+                .any(|paragraph| paragraph.shown != Shown::Always)
+        }) {
+            return Err(choice_error(ParseErrorType::ShownChoice, choice));
+        }
 
-        let len = section.choices.len();
-        for choice in &mut section.choices {
-            if let "__RESTART" | "__MENU" = choice.goto.0.as_str() {
-                return Err(ParseError::new(
-                    ParseErrorType::ReservedKeyUsage,
-                    line_num.unwrap(),
-                    current_line.unwrap().to_owned(),
-                ));
+        let text: String = description
+            .iter()
+            .map(|(_, line)| format!("{}\n", line))
+            .collect();
+        let description = Description::new(&text);
+
+        let len = choices.len();
+        for choice in &mut choices {
+            for outcome in &choice.outcomes {
+                if let "__RESTART" | "__MENU" = outcome.goto.0.as_str() {
+                    return Err(choice_error(ParseErrorType::ReservedKeyUsage, choice));
+                }
+            }
+
+            if choice.is_random() && choice.gotos().any(|goto| goto.0 == "END") {
+                return Err(choice_error(ParseErrorType::InvalidEnd, choice));
+            }
+            // a single `TARGET 100%` is fine, if pointless
+            let mut chances = choice.outcomes.iter().map(|outcome| outcome.chance);
+            if chances.clone().any(|chance| chance == 0)
+                || chances.try_fold(0, u32::checked_add) != Some(100)
+            {
+                return Err(choice_error(ParseErrorType::InvalidChances, choice));
             }
 
             if choice.description.to_string().is_empty() {
                 if len != 1 {
-                    return Err(ParseError::new(
-                        ParseErrorType::ChoiceShorthandNotLone,
-                        line_num.unwrap(),
-                        current_line.unwrap().to_owned(),
-                    ));
+                    return Err(choice_error(ParseErrorType::ChoiceShorthandNotLone, choice));
+                } else if choice.once {
+                    return Err(choice_error(ParseErrorType::OnceShorthand, choice));
                 }
 
-                if choice.goto.0 == "END" {
-                    section.choices = vec![
-                        Choice::parse("Restart from beginning -> __RESTART", line_num.unwrap())
+                if choice.goto().0 == "END" {
+                    choices = vec![
+                        Choice::parse("Restart from beginning -> __RESTART", line_num)
                             .expect("manually verified"),
-                        Choice::parse("Return to menu -> __MENU", line_num.unwrap())
+                        Choice::parse("Return to menu -> __MENU", line_num)
                             .expect("manually verified"),
                     ];
                 } else {
                     choice.description = Description::new("Continue...");
                 }
                 break;
-            } else if choice.goto.0 == "END" {
-                return Err(ParseError::new(
-                    ParseErrorType::InvalidEnd,
-                    line_num.unwrap(),
-                    current_line.unwrap().to_owned(),
-                ));
+            } else if choice.goto().0 == "END" {
+                return Err(choice_error(ParseErrorType::InvalidEnd, choice));
             }
         }
 
-        Ok(section)
+        Ok(Self {
+            identifier,
+            description,
+            choices,
+            line_num,
+            file: None,
+        })
+    }
+
+    pub fn identifier(&self) -> &SectionIdentifier {
+        &self.identifier
+    }
+
+    pub fn description(&self) -> &Description {
+        &self.description
+    }
+
+    pub fn choices(&self) -> &[Choice] {
+        &self.choices
+    }
+
+    /// the line of the section's `NAME:` header
+    pub fn line_num(&self) -> usize {
+        self.line_num
+    }
+
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    /// where the section is, as `file:line`, or `line N` if it isn't from a
+    /// file. lines count from 1
+    pub fn location(&self) -> String {
+        match &self.file {
+            Some(file) => format!("{}:{}", file.display(), self.line_num + 1),
+            None => format!("line {}", self.line_num + 1),
+        }
+    }
+
+    /// whether the section ends the story, i.e. it was written with `-> END`
+    /// and its choices were expanded to restarting and returning to the menu
+    pub fn is_ending(&self) -> bool {
+        self.choices.iter().any(Choice::is_special)
     }
 }
 
@@ -272,7 +847,8 @@ impl Display for Section {
         {}
     </span>
 </div>"#,
-                choice.goto, choice.description
+                choice.target(),
+                choice.description
             )?;
         }
 
@@ -280,19 +856,45 @@ impl Display for Section {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, PartialOrd, Ord, Eq)]
-struct Choice {
+#[derive(Clone, Debug)]
+pub struct Choice {
     description: Description,
-    goto: SectionIdentifier,
+    // just the one, unless it's random
+    outcomes: Vec<Outcome>,
+    // whether it goes away once it's been taken, as `* take the key -> HALL`
+    once: bool,
+    // where it was written, for errors
+    line_num: usize,
+}
+
+// where a choice was written doesn't change what it is
+impl PartialEq for Choice {
+    fn eq(&self, other: &Self) -> bool {
+        self.meaning() == other.meaning()
+    }
+}
+
+impl Eq for Choice {}
+
+impl PartialOrd for Choice {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Choice {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.meaning().cmp(&other.meaning())
+    }
 }
 
 impl Choice {
-    fn parse(str: &str, line_num: usize) -> ParseResult<Self> {
+    pub(crate) fn parse(str: &str, line_num: usize) -> ParseResult<Self> {
         // we want to parse it as the following: any characters, until we get
         // to an ->, and then a section identifier
         // any requirements on the description? pretty much nothing
 
-        let mut parts: Vec<&str> = str.split("->").collect();
+        let parts: Vec<&str> = str.split("->").collect();
 
         if (parts.len() < 2) || parts[0].ends_with('\\') {
             return Err(ParseError::new(
@@ -315,16 +917,168 @@ impl Choice {
             // parts[0] = "Continue...";
         }
 
-        let goto = SectionIdentifier::parse((line_num, parts[1]), false)?;
-        let description = Description::new(parts[0]);
+        // `* ` makes it once-only, and `+ ` sticky, which it is anyway
+        let (description, once) = match parts[0].split_at_checked(1) {
+            Some(("*", rest)) if rest.starts_with(char::is_whitespace) => (rest, true),
+            Some(("+", rest)) if rest.starts_with(char::is_whitespace) => (rest, false),
+            _ => (parts[0], false),
+        };
+
+        let outcomes = Outcome::parse_all((line_num, parts[1]))?;
 
-        Ok(Self { description, goto })
+        Ok(Self::random(description, outcomes)
+            .with_once(once)
+            .on_line(line_num))
+    }
+
+    pub(crate) fn new(description: &str, goto: SectionIdentifier) -> Self {
+        Self::random(description, vec![Outcome { goto, chance: 100 }])
+    }
+
+    // the chances get checked along with the rest of the section
+    pub(crate) fn random(description: &str, outcomes: Vec<Outcome>) -> Self {
+        Self {
+            description: Description::new(description),
+            outcomes,
+            once: false,
+            line_num: 0,
+        }
+    }
+
+    pub(crate) fn with_once(mut self, once: bool) -> Self {
+        self.once = once;
+        self
+    }
+
+    pub(crate) fn on_line(mut self, line_num: usize) -> Self {
+        self.line_num = line_num;
+        self
+    }
+
+    fn meaning(&self) -> (&Description, &[Outcome], bool) {
+        (&self.description, &self.outcomes, self.once)
+    }
+
+    pub fn description(&self) -> &Description {
+        &self.description
+    }
+
+    /// the description, as it's shown to the reader
+    pub fn label(&self) -> String {
+        let texts: Vec<&str> = self.description.0.iter().map(Paragraph::text).collect();
+        texts.join(" ")
+    }
+
+    /// where the choice goes. a random choice could go to any of its
+    /// [`Choice::outcomes`], and this is just the first of them
+    pub fn goto(&self) -> &SectionIdentifier {
+        &self.outcomes[0].goto
+    }
+
+    /// every section the choice could go to, with the chance of each. a
+    /// choice that isn't random has the one, at 100%
+    pub fn outcomes(&self) -> &[Outcome] {
+        &self.outcomes
+    }
+
+    /// every section the choice could go to
+    pub fn gotos(&self) -> impl Iterator<Item = &SectionIdentifier> {
+        self.outcomes.iter().map(Outcome::goto)
+    }
+
+    /// whether the choice goes to one of several sections at random, as in
+    /// `flip -> HEADS 50% | TAILS 50%`
+    pub fn is_random(&self) -> bool {
+        self.outcomes.len() > 1
+    }
+
+    /// where the choice goes, as it'd be written after the `->`
+    pub fn target(&self) -> String {
+        if self.is_random() {
+            let outcomes: Vec<String> = self.outcomes.iter().map(Outcome::to_string).collect();
+            outcomes.join(" | ")
+        } else {
+            self.goto().to_string()
+        }
+    }
+
+    /// whether the choice goes away after it's been taken, as in
+    /// `* take the key -> HALL`. otherwise it's sticky, and offered every
+    /// time the section is
+    pub fn is_once(&self) -> bool {
+        self.once
+    }
+
+    /// whether the choice goes to `__RESTART` or `__MENU` rather than a section
+    pub fn is_special(&self) -> bool {
+        matches!(self.goto().0.as_str(), "__RESTART" | "__MENU")
+    }
+}
+
+/// one of the sections a choice can go to, and the percentage chance of
+/// going there
+#[derive(Clone, Debug, PartialEq, PartialOrd, Ord, Eq)]
+pub struct Outcome {
+    goto: SectionIdentifier,
+    chance: u32,
+}
+
+impl Outcome {
+    /// what's after the `->` of a choice: either `TARGET`, or outcomes like
+    /// `HEADS 50% | TAILS 50%`. the chances aren't checked here, since that's
+    /// part of checking the section
+    pub fn parse_all((line_num, str): (usize, &str)) -> ParseResult<Vec<Self>> {
+        if !str.contains(['|', '%']) {
+            let goto = SectionIdentifier::parse((line_num, str), false)?;
+            return Ok(vec![Self { goto, chance: 100 }]);
+        }
+
+        str.split('|')
+            .map(|outcome| {
+                let error = || {
+                    ParseError::new(
+                        ParseErrorType::InvalidOutcome,
+                        line_num,
+                        outcome.trim().to_owned(),
+                    )
+                };
+
+                let (goto, chance) = outcome
+                    .trim()
+                    .rsplit_once(char::is_whitespace)
+                    .ok_or_else(error)?;
+                let chance = chance
+                    .strip_suffix('%')
+                    .and_then(|chance| chance.parse().ok())
+                    .ok_or_else(error)?;
+
+                Ok(Self {
+                    goto: SectionIdentifier::parse((line_num, goto), false)?,
+                    chance,
+                })
+            })
+            .collect()
+    }
+
+    pub fn goto(&self) -> &SectionIdentifier {
+        &self.goto
+    }
+
+    /// out of 100
+    pub fn chance(&self) -> u32 {
+        self.chance
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}%", self.goto, self.chance)
     }
 }
 
 // just a newtype, that's all caps, numeric, and underscores
 // must have *some* alphabetic. can't be all underscores/numeric
-#[derive(Hash, Clone, Debug, PartialEq, PartialOrd, Ord, Eq)]
+#[derive(Hash, Clone, Debug, PartialEq, PartialOrd, Ord, Eq, Serialize)]
 pub struct SectionIdentifier(String);
 
 // TODO: figure out solution for these being public
@@ -334,10 +1088,12 @@ impl SectionIdentifier {
     pub fn parse((line_num, mut str): (usize, &str), def: bool) -> ParseResult<Self> {
         let mut found_alphabetic = false;
         let mut colon = false;
+        let mut chapter = false;
 
         str = str.trim();
 
-        for ch in str.chars() {
+        let mut chars = str.chars().peekable();
+        while let Some(ch) = chars.next() {
             if ((ch.is_ascii_alphabetic() && ch.is_ascii_uppercase())
                 || ch.is_ascii_digit()
                 || ch == '_')
@@ -348,6 +1104,17 @@ impl SectionIdentifier {
                 if ch.is_ascii_alphabetic() {
                     found_alphabetic = true;
                 }
+            } else if ch == ':'
+                && chars.peek() == Some(&':')
+                && !colon
+                && !chapter
+                && found_alphabetic
+            {
+                // `CHAPTER::NAME` --- only one level of chapters, and the
+                // chapter has to be a valid identifier in its own right
+                chars.next();
+                chapter = true;
+                found_alphabetic = false;
             } else if ch == ':' && !colon {
                 colon = true;
             } else {
@@ -389,6 +1156,48 @@ impl SectionIdentifier {
     }
 }
 
+impl SectionIdentifier {
+    /// whether this is one of the identifiers with special meaning, which
+    /// can't be used to name a section
+    pub fn is_reserved(&self) -> bool {
+        matches!(self.name(), "END" | "__RESTART" | "__MENU")
+    }
+
+    /// the chapter a section is in, for `CHAPTER::NAME`
+    pub fn chapter(&self) -> Option<&str> {
+        self.0.split_once("::").map(|(chapter, _)| chapter)
+    }
+
+    /// the identifier without its chapter
+    pub fn name(&self) -> &str {
+        self.0.split_once("::").map_or(&self.0, |(_, name)| name)
+    }
+
+    pub(crate) fn in_chapter(&self, chapter: &str) -> Self {
+        Self(format!("{}::{}", chapter, self.name()))
+    }
+
+    // an unqualified goto refers to the section of that name in the same
+    // chapter, if there is one, and otherwise to the one outside of any
+    // chapter. qualified gotos are always taken as-is
+    pub(crate) fn resolve(
+        &self,
+        chapter: Option<&str>,
+        exists: impl Fn(&SectionIdentifier) -> bool,
+    ) -> Option<SectionIdentifier> {
+        if let Some(chapter) = chapter
+            && self.chapter().is_none()
+        {
+            let qualified = self.in_chapter(chapter);
+            if exists(&qualified) {
+                return Some(qualified);
+            }
+        }
+
+        exists(self).then(|| self.clone())
+    }
+}
+
 impl Display for SectionIdentifier {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -396,7 +1205,7 @@ impl Display for SectionIdentifier {
 }
 
 #[derive(Clone, Debug, PartialEq, PartialOrd, Ord, Eq)]
-struct Description(Vec<Paragraph>);
+pub struct Description(Vec<Paragraph>);
 
 impl Description {
     fn new(str: &str) -> Self {
@@ -409,13 +1218,14 @@ impl Description {
             .map(|line| format!("{}\n", line.trim()))
             .collect();
 
-        let desc = str
-            .split("\n\n")
-            .map(|paragraph| Paragraph::new(paragraph))
-            .collect();
+        let desc = str.split("\n\n").map(Paragraph::new).collect();
 
         Self(desc)
     }
+
+    pub fn paragraphs(&self) -> &[Paragraph] {
+        &self.0
+    }
 }
 
 impl Display for Description {
@@ -431,21 +1241,78 @@ impl Display for Description {
 }
 
 #[derive(Clone, Debug, PartialEq, PartialOrd, Ord, Eq)]
-struct Paragraph(String);
+pub struct Paragraph {
+    text: String,
+    shown: Shown,
+}
+
+/// which visits to its section a paragraph is shown on
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Ord, Eq, Hash)]
+pub enum Shown {
+    Always,
+    /// the first visit, for a paragraph starting with `[first]`
+    First,
+    /// every visit after the first, for a paragraph starting with `[again]`
+    Again,
+}
 
 impl Paragraph {
     fn new(str: &str) -> Self {
-        Self(str.replace('\n', " ").trim().to_owned())
+        let text = str.replace('\n', " ");
+        let text = text.trim();
+
+        let (text, shown) = if let Some(rest) = text.strip_prefix("[first]") {
+            (rest, Shown::First)
+        } else if let Some(rest) = text.strip_prefix("[again]") {
+            (rest, Shown::Again)
+        } else {
+            (text, Shown::Always)
+        };
+
+        Self {
+            text: text.trim_start().to_owned(),
+            shown,
+        }
+    }
+
+    /// the text, without any `[first]` or `[again]`
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn shown(&self) -> Shown {
+        self.shown
+    }
+
+    /// whether it's shown on the `visit`th visit to its section, counting
+    /// from 1
+    pub fn is_shown(&self, visit: usize) -> bool {
+        match self.shown {
+            Shown::Always => true,
+            Shown::First => visit <= 1,
+            Shown::Again => visit > 1,
+        }
     }
 }
 
+// as it's written, so with any `[first]` or `[again]`
 impl Display for Paragraph {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        let marker = match self.shown {
+            Shown::Always => return write!(f, "{}", self.text),
+            Shown::First => "[first]",
+            Shown::Again => "[again]",
+        };
+
+        if self.text.is_empty() {
+            write!(f, "{}", marker)
+        } else {
+            write!(f, "{} {}", marker, self.text)
+        }
     }
 }
 
-type ParseResult<T> = Result<T, ParseError>;
+pub type ParseResult<T> = Result<T, ParseError>;
 
 // #[derive(Clone, Debug, PartialEq, PartialOrd, Ord, Eq)]
 #[derive(Debug)]
@@ -454,21 +1321,58 @@ pub struct ParseError {
     /// starting from 0, normalized to 1 when rendering
     line_num: usize,
     text: String,
+    /// the file the error is in, if the story was loaded from files
+    file: Option<PathBuf>,
 }
 
 impl ParseError {
-    fn new(error_type: ParseErrorType, line_num: usize, text: String) -> Self {
+    pub(crate) fn new(error_type: ParseErrorType, line_num: usize, text: String) -> Self {
         Self {
             error_type,
             line_num,
             text,
+            file: None,
+        }
+    }
+
+    // errors are created without knowing what file they're in, so it gets
+    // filled in on the way out --- the innermost file wins, for includes
+    pub fn in_file(mut self, file: Option<&Path>) -> Self {
+        if self.file.is_none() {
+            self.file = file.map(Path::to_path_buf);
         }
+        self
+    }
+
+    pub fn error_type(&self) -> &ParseErrorType {
+        &self.error_type
+    }
+
+    pub fn line_num(&self) -> usize {
+        self.line_num
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
     }
 }
 
 impl Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.error_type)
+        match &self.file {
+            Some(file) => write!(f, "{}:{}: ", file.display(), self.line_num + 1)?,
+            None => write!(f, "line {}: ", self.line_num + 1)?,
+        }
+        write!(f, "{}", self.error_type)?;
+
+        if !self.text.is_empty() {
+            write!(f, "\n    {}", self.text)?;
+        }
+        Ok(())
     }
 }
 
@@ -485,19 +1389,89 @@ pub enum ParseErrorType {
     MultipleArrows,
     ExpectedChoice,
     ExpectedDescription,
-    DuplicateSections(Section, Section),
+    DuplicateSections(Box<Section>, Box<Section>),
     DanglingGoto,
     InvalidEnd,
     ChoiceShorthandNotLone,
     ReservedKeyUsage,
+    UnknownMetadataKey(String),
+    DuplicateMetadataKey(String),
+    UnterminatedMetadata,
+    MissingStart,
+    InvalidInclude,
+    IncludeFailed(PathBuf, io::Error),
+    IncludeCycle(PathBuf),
+    InvalidChapter,
+    InvalidOutcome,
+    InvalidChances,
+    OnceShorthand,
+    ShownChoice,
+    LineBreak,
 }
 
 impl Display for ParseErrorType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // match self {
-        //     ParseError::Foo => write!(f, "FOO"),
-        // }
-        todo!()
+        match self {
+            Self::SectionIdentifier(ch) => write!(
+                f,
+                "invalid character '{}' in section identifier, which can only contain uppercase letters, digits, and underscores",
+                ch
+            ),
+            Self::UnexpectedColon => write!(f, "unexpected colon after section identifier"),
+            Self::MissingColon => write!(f, "missing colon"),
+            Self::MissingAlphabetic => {
+                write!(f, "section identifier must contain at least one letter")
+            }
+            Self::UnexpectedSeparator => write!(f, "unexpected `---` separator"),
+            Self::MissingArrow => write!(f, "expected a choice, but there's no `->`"),
+            Self::MultipleArrows => write!(f, "choice contains more than one `->`"),
+            Self::ExpectedChoice => write!(f, "expected a choice"),
+            Self::ExpectedDescription => write!(f, "expected a description"),
+            Self::DuplicateSections(section, dup_section) => write!(
+                f,
+                "section {} is defined twice, at {} and {}",
+                section.identifier,
+                dup_section.location(),
+                section.location()
+            ),
+            Self::DanglingGoto => write!(f, "choice goes to a section that doesn't exist"),
+            Self::InvalidEnd => {
+                write!(f, "`-> END` must be the only choice, without a description")
+            }
+            Self::ChoiceShorthandNotLone => {
+                write!(f, "a choice without a description must be the only choice")
+            }
+            Self::ReservedKeyUsage => write!(f, "END, __RESTART, and __MENU are reserved"),
+            Self::UnknownMetadataKey(key) => write!(f, "unknown metadata key `{}`", key),
+            Self::DuplicateMetadataKey(key) => write!(f, "metadata key `{}` given twice", key),
+            Self::UnterminatedMetadata => write!(f, "metadata header is missing its closing `---`"),
+            Self::MissingStart => write!(f, "the start section doesn't exist"),
+            Self::InvalidInclude => write!(f, "expected `include \"path\"`"),
+            Self::IncludeFailed(path, err) => {
+                write!(f, "couldn't include {}: {}", path.display(), err)
+            }
+            Self::IncludeCycle(path) => {
+                write!(f, "{} ends up including itself", path.display())
+            }
+            Self::InvalidChapter => write!(f, "chapter names can't be nested or reserved"),
+            Self::InvalidOutcome => {
+                write!(
+                    f,
+                    "expected `SECTION 50%` for each outcome of a random choice"
+                )
+            }
+            Self::InvalidChances => write!(
+                f,
+                "the chances of a random choice must each be more than 0%, and add up to 100%"
+            ),
+            Self::OnceShorthand => {
+                write!(f, "a choice without a description can't be once-only")
+            }
+            Self::ShownChoice => {
+                write!(f, "only paragraphs can start with `[first]` or `[again]`")
+            }
+            Self::LineBreak => write!(f, "metadata and choices can't have line breaks"),
+        }
     }
 }
 
@@ -513,13 +1487,201 @@ START:
 this is a description
 
 yes -> FIRST
-no -> SECOND",
+no -> SECOND
+---
+FIRST:
+first
+-> END
+---
+SECOND:
+second
+-> END
+---",
         )
         .unwrap();
 
+        assert_eq!(story.sections().len(), 3);
+        assert_eq!(story.metadata(), &Metadata::default());
+
         // TODO: other stuff
     }
 
+    #[test]
+    fn metadata() {
+        let story = Story::parse(
+            "\
+---
+title: The Cliff
+author: Someone
+version: 1.2
+start: CLIFF
+---
+
+CLIFF:
+Believe you can fly?
+-> END
+---",
+        )
+        .unwrap();
+
+        let metadata = story.metadata();
+        assert_eq!(metadata.title(), Some("The Cliff"));
+        assert_eq!(metadata.author(), Some("Someone"));
+        assert_eq!(metadata.version(), Some("1.2"));
+        assert_eq!(metadata.language(), None);
+        assert_eq!(metadata.start(), &SectionIdentifier("CLIFF".to_owned()));
+
+        // the default start section doesn't exist here
+        assert!(matches!(
+            Story::parse("CLIFF:\nfly?\n-> END\n---")
+                .unwrap_err()
+                .error_type,
+            ParseErrorType::MissingStart
+        ));
+        assert!(matches!(
+            Story::parse("---\nfoo: bar\n---\nSTART:\nfoo\n-> END\n---")
+                .unwrap_err()
+                .error_type,
+            ParseErrorType::UnknownMetadataKey(_)
+        ));
+        assert!(matches!(
+            Story::parse("---\ntitle: foo\nauthor: bar")
+                .unwrap_err()
+                .error_type,
+            ParseErrorType::UnterminatedMetadata
+        ));
+    }
+
+    #[test]
+    fn include() {
+        let mut loader = MemoryLoader::default();
+        loader.insert(
+            "story/main.fater",
+            "START:\nbegin\n-> CLIFF\n---\ninclude \"chapters/cliff.fater\"".to_owned(),
+        );
+        loader.insert(
+            "story/chapters/cliff.fater",
+            "CLIFF:\nfly?\nyes -> FLY\nno -> START\n---\ninclude \"../fly.fater\"".to_owned(),
+        );
+        loader.insert("story/fly.fater", "FLY:\nyou can't\n-> END\n---".to_owned());
+
+        let story = load_file_with("story/main.fater", &mut loader)
+            .unwrap()
+            .unwrap();
+        // in the order they were written, with included sections in place
+        let order: Vec<String> = story.sections().keys().map(ToString::to_string).collect();
+        assert_eq!(order, vec!["START", "CLIFF", "FLY"]);
+        assert_eq!(
+            story.sections()[&SectionIdentifier("FLY".to_owned())].file,
+            Some(PathBuf::from("story/fly.fater"))
+        );
+        assert_eq!(
+            includes(
+                &loader.load(Path::new("story/main.fater")).unwrap(),
+                Path::new("story/main.fater")
+            ),
+            vec![PathBuf::from("story/chapters/cliff.fater")]
+        );
+
+        // errors are reported in the included file
+        loader.insert(
+            "story/fly.fater",
+            "FLY:\nyou can't\n-> NOWHERE\n---".to_owned(),
+        );
+        let err = load_file_with("story/main.fater", &mut loader)
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(err.error_type, ParseErrorType::DanglingGoto));
+        assert_eq!(err.file(), Some(Path::new("story/fly.fater")));
+        // the line the choice is on
+        assert_eq!(err.line_num(), 2);
+
+        // both definitions of a duplicate are pointed at
+        loader.insert(
+            "story/fly.fater",
+            "FLY:\nyou can't\n-> END\n---\nSTART:\nagain\n-> END\n---".to_owned(),
+        );
+        let err = load_file_with("story/main.fater", &mut loader)
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(
+            err.error_type,
+            ParseErrorType::DuplicateSections(..)
+        ));
+        assert_eq!(
+            err.error_type.to_string(),
+            "section START is defined twice, at story/main.fater:1 and story/fly.fater:5"
+        );
+
+        loader.insert(
+            "story/fly.fater",
+            "FLY:\nyou can't\n-> END\n---\ninclude \"main.fater\"".to_owned(),
+        );
+        let err = load_file_with("story/main.fater", &mut loader)
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(err.error_type, ParseErrorType::IncludeCycle(_)));
+        assert_eq!(err.file(), Some(Path::new("story/fly.fater")));
+
+        // there's nothing to include relative to
+        assert!(matches!(
+            Story::parse("include \"foo.fater\"")
+                .unwrap_err()
+                .error_type,
+            ParseErrorType::IncludeFailed(..)
+        ));
+    }
+
+    #[test]
+    fn chapter() {
+        let story = Story::parse(
+            "\
+START:
+begin
+-> CH1::START
+---
+chapter CH1
+
+START:
+the first chapter
+cliff -> CLIFF
+next -> CH2::CLIFF
+---
+CLIFF:
+relative
+-> END
+---
+CH2::CLIFF:
+qualified
+back -> START
+---",
+        )
+        .unwrap();
+
+        let choices = |identifier: &str| {
+            story.sections()[&SectionIdentifier::parse((0, identifier), false).unwrap()]
+                .choices
+                .iter()
+                .map(|choice| choice.goto().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(choices("START"), vec!["CH1::START"]);
+        assert_eq!(choices("CH1::START"), vec!["CH1::CLIFF", "CH2::CLIFF"]);
+        // nothing called START in CH2, so it falls back to the top level
+        assert_eq!(choices("CH2::CLIFF"), vec!["START"]);
+
+        assert!(matches!(
+            Story::parse("START:\nfoo\n-> CH1::FOO\n---\nchapter CH2\nFOO:\nfoo\n-> END")
+                .unwrap_err()
+                .error_type,
+            ParseErrorType::DanglingGoto
+        ));
+        assert!(matches!(
+            Story::parse("chapter CH1::CH2").unwrap_err().error_type,
+            ParseErrorType::InvalidChapter
+        ));
+    }
+
     #[test]
     fn section() {
         assert_eq!(
@@ -540,20 +1702,29 @@ baz buz -> BOB_MARLEY
             .unwrap(),
             Section {
                 identifier: SectionIdentifier("MU".to_owned()),
-                description: Description(vec![
-                    Paragraph("foo bar".to_owned()),
-                    Paragraph("baz".to_owned())
-                ]),
+                description: Description(vec![Paragraph::new("foo bar"), Paragraph::new("baz")]),
                 choices: vec![
                     Choice {
-                        description: Description(vec![Paragraph("foobar".to_owned())]),
-                        goto: SectionIdentifier("ALICE".to_owned())
+                        description: Description(vec![Paragraph::new("foobar")]),
+                        outcomes: vec![Outcome {
+                            goto: SectionIdentifier("ALICE".to_owned()),
+                            chance: 100
+                        }],
+                        once: false,
+                        line_num: 6,
                     },
                     Choice {
-                        description: Description(vec![Paragraph("baz buz".to_owned())]),
-                        goto: SectionIdentifier("BOB_MARLEY".to_owned())
+                        description: Description(vec![Paragraph::new("baz buz")]),
+                        outcomes: vec![Outcome {
+                            goto: SectionIdentifier("BOB_MARLEY".to_owned()),
+                            chance: 100
+                        }],
+                        once: false,
+                        line_num: 7,
                     }
-                ]
+                ],
+                line_num: 0,
+                file: None,
             }
         )
     }
@@ -563,10 +1734,106 @@ baz buz -> BOB_MARLEY
         assert_eq!(
             Choice::parse("foo baz -> BAR", 0).unwrap(),
             Choice {
-                description: Description(vec![Paragraph("foo baz".to_owned())]),
-                goto: SectionIdentifier("BAR".to_owned())
+                description: Description(vec![Paragraph::new("foo baz")]),
+                outcomes: vec![Outcome {
+                    goto: SectionIdentifier("BAR".to_owned()),
+                    chance: 100
+                }],
+                once: false,
+                line_num: 0,
             }
         );
+
+        let choice = Choice::parse("flip -> HEADS 50%|  CH1::TAILS 50% ", 0).unwrap();
+        assert!(choice.is_random());
+        assert_eq!(
+            choice.outcomes(),
+            [
+                Outcome {
+                    goto: SectionIdentifier("HEADS".to_owned()),
+                    chance: 50
+                },
+                Outcome {
+                    goto: SectionIdentifier("CH1::TAILS".to_owned()),
+                    chance: 50
+                }
+            ]
+        );
+        assert_eq!(choice.target(), "HEADS 50% | CH1::TAILS 50%");
+
+        assert!(Choice::parse("flip -> HEADS 50% | TAILS", 0).is_err());
+        assert!(Choice::parse("flip -> HEADS 50% | TAILS half%", 0).is_err());
+        assert!(Choice::parse("flip -> HEADS 50% | tails 50%", 0).is_err());
+
+        let once = Choice::parse("*  take the key -> HALL", 0).unwrap();
+        assert!(once.is_once());
+        assert_eq!(once.description().to_string(), "take the key");
+        let sticky = Choice::parse("+ look around -> HALL", 0).unwrap();
+        assert!(!sticky.is_once());
+        assert_eq!(sticky.description().to_string(), "look around");
+        // only with a space after
+        let emphasis = Choice::parse("*really* jump -> CLIFF", 0).unwrap();
+        assert!(!emphasis.is_once());
+        assert_eq!(emphasis.description().to_string(), "*really* jump");
+
+        assert!(matches!(
+            Story::parse("START:\nfoo\n* -> START\n---")
+                .unwrap_err()
+                .error_type,
+            ParseErrorType::OnceShorthand
+        ));
+
+        // errors in a choice are on its line
+        let err = Story::parse("START:\nfoo\nyes -> END\nno -> START\n---").unwrap_err();
+        assert!(matches!(err.error_type, ParseErrorType::InvalidEnd));
+        assert_eq!(err.line_num, 2);
+
+        // choices can't come and go with visits, except by being once-only
+        let err = Story::parse("START:\nfoo\n[first] go -> START\n---").unwrap_err();
+        assert!(matches!(err.error_type, ParseErrorType::ShownChoice));
+        assert_eq!(err.line_num, 2);
+    }
+
+    #[test]
+    fn random() {
+        let story = |choice: &str| {
+            Story::parse(&format!(
+                "START:\nflip a coin\n{}\n---\nHEADS:\nheads\n-> END\n---\nTAILS:\ntails\n-> END\n---",
+                choice
+            ))
+        };
+
+        let story_30_70 = story("flip -> HEADS 30% | TAILS 70%").unwrap();
+        let start = &story_30_70.sections()[story_30_70.metadata().start()];
+        let chances: Vec<u32> = start.choices()[0]
+            .outcomes()
+            .iter()
+            .map(Outcome::chance)
+            .collect();
+        assert_eq!(chances, [30, 70]);
+        assert!(story("-> HEADS 50% | TAILS 50%").is_ok());
+
+        let error = |choice| story(choice).unwrap_err().error_type;
+        for choice in [
+            "flip -> HEADS 50% | TAILS 40%",
+            "flip -> HEADS 100% | TAILS 0%",
+            "flip -> HEADS 4294967295% | TAILS 1%",
+            "flip -> HEADS 50%",
+        ] {
+            assert!(matches!(error(choice), ParseErrorType::InvalidChances));
+        }
+        assert!(matches!(
+            error("flip -> HEADS 50% | END 50%"),
+            ParseErrorType::InvalidEnd
+        ));
+        assert!(matches!(
+            error("flip -> HEADS 50% | __MENU 50%"),
+            ParseErrorType::ReservedKeyUsage
+        ));
+        assert!(matches!(
+            error("flip -> HEADS 50% | TIALS 50%"),
+            ParseErrorType::DanglingGoto
+        ));
     }
 
     #[test]
@@ -579,21 +1846,37 @@ baz buz -> BOB_MARLEY
             SectionIdentifier::parse((0, "FOO:"), true).unwrap(),
             SectionIdentifier("FOO".to_owned())
         );
+        assert_eq!(
+            SectionIdentifier::parse((0, "CH2::FOO:"), true).unwrap(),
+            SectionIdentifier("CH2::FOO".to_owned())
+        );
+        assert!(SectionIdentifier::parse((0, "CH2::FOO::BAR"), false).is_err());
+        assert!(SectionIdentifier::parse((0, "::FOO"), false).is_err());
+        assert!(SectionIdentifier::parse((0, "CH2::"), false).is_err());
     }
 
     #[test]
     fn description() {
         assert_eq!(
             Description::new("far bar\nbaz\n\nfoobar\nquz").0,
-            vec![
-                Paragraph("far bar baz".to_owned()),
-                Paragraph("foobar quz".to_owned())
-            ]
+            vec![Paragraph::new("far bar baz"), Paragraph::new("foobar quz")]
         );
     }
 
     #[test]
     fn paragraph() {
-        assert_eq!(Paragraph::new("  foo\nbar baz  ").0, "foo bar baz");
+        assert_eq!(Paragraph::new("  foo\nbar baz  ").text, "foo bar baz");
+
+        let first = Paragraph::new("[first]  foo\nbar");
+        assert_eq!(first.text(), "foo bar");
+        assert_eq!(first.shown(), Shown::First);
+        assert_eq!(first.to_string(), "[first] foo bar");
+        assert!(first.is_shown(1) && !first.is_shown(2));
+
+        let again = Paragraph::new("[again] foo");
+        assert_eq!(again.shown(), Shown::Again);
+        assert!(!again.is_shown(1) && again.is_shown(2));
+        // only at the start
+        assert_eq!(Paragraph::new("foo [first]").shown(), Shown::Always);
     }
 }
@@ -0,0 +1,165 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    parse::{self, FileLoader, SectionIdentifier},
+    symbols::{RenameError, Symbols},
+    syntax::SyntaxTree,
+};
+
+/// renames a section of a story, rewriting its `NAME:` header and every
+/// choice that goes to it, and leaving the rest of the text exactly as it was
+pub fn rename_section(source: &str, old: &str, new: &str) -> Result<String, RenameError> {
+    let (old, new) = identifiers(old, new)?;
+    let tree = SyntaxTree::parse(source);
+
+    let edits = Symbols::new([(None, &tree)]).rename(&old, &new)?;
+    Ok(tree.edit(edits.into_iter().map(|edit| (edit.span, edit.text))))
+}
+
+/// renames a section across a story and every file it includes, returning
+/// the new text of each file that changed
+pub fn rename_section_in_files(
+    path: &Path,
+    old: &str,
+    new: &str,
+    loader: &mut impl FileLoader,
+) -> Result<Vec<(PathBuf, String)>, RenameError> {
+    let (old, new) = identifiers(old, new)?;
+
+    let mut files: Vec<(PathBuf, SyntaxTree)> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = vec![path.to_path_buf()];
+    while let Some(path) = queue.pop() {
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+        let source = loader
+            .load(&path)
+            .map_err(|err| RenameError::ReadFailed(path.clone(), err))?;
+
+        // backwards, so they're visited in order
+        queue.extend(parse::includes(&source, &path).into_iter().rev());
+        files.push((path, SyntaxTree::parse(&source)));
+    }
+
+    let symbols = Symbols::new(
+        files
+            .iter()
+            .map(|(path, tree)| (Some(path.as_path()), tree)),
+    );
+    let edits = symbols.rename(&old, &new)?;
+
+    Ok(files
+        .iter()
+        .filter_map(|(path, tree)| {
+            let edits: Vec<_> = edits
+                .iter()
+                .filter(|edit| edit.file.as_deref() == Some(path))
+                .map(|edit| (edit.span, edit.text.clone()))
+                .collect();
+            (!edits.is_empty()).then(|| (path.clone(), tree.edit(edits)))
+        })
+        .collect())
+}
+
+fn identifiers(
+    old: &str,
+    new: &str,
+) -> Result<(SectionIdentifier, SectionIdentifier), RenameError> {
+    let parse = |identifier| {
+        SectionIdentifier::parse((0, identifier), false).map_err(RenameError::InvalidIdentifier)
+    };
+    Ok((parse(old)?, parse(new)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{MemoryLoader, Story};
+
+    const STORY: &str = "\
+# where it all begins
+START:
+Believe   you can fly?
+yes -> FLY
+no  ->  START
+---
+
+FLY:
+You can't.
+-> END
+---
+";
+
+    #[test]
+    fn rename_section() {
+        let renamed = super::rename_section(STORY, "FLY", "JUMP").unwrap();
+        assert_eq!(renamed, STORY.replace("FLY", "JUMP"));
+        assert!(Story::parse(&renamed).is_ok());
+
+        assert!(matches!(
+            super::rename_section(STORY, "FLY", "jump"),
+            Err(RenameError::InvalidIdentifier(_))
+        ));
+        assert!(matches!(
+            super::rename_section(STORY, "FLY", "__MENU"),
+            Err(RenameError::Reserved)
+        ));
+        assert!(matches!(
+            super::rename_section(STORY, "FLY", "START"),
+            Err(RenameError::AlreadyExists)
+        ));
+    }
+
+    #[test]
+    fn rename_section_in_files() {
+        let mut loader = MemoryLoader::default();
+        loader.insert(
+            "story/main.fater",
+            "START:\nbegin\n-> FLY\n---\ninclude \"fly.fater\"\n".to_owned(),
+        );
+        loader.insert(
+            "story/fly.fater",
+            "FLY:\nyou can't\n-> END\n---\n".to_owned(),
+        );
+        loader.insert(
+            "story/other.fater",
+            "FLY:\nnot included\n-> END\n---\n".to_owned(),
+        );
+
+        let renamed = super::rename_section_in_files(
+            Path::new("story/main.fater"),
+            "FLY",
+            "JUMP",
+            &mut loader,
+        )
+        .unwrap();
+        assert_eq!(
+            renamed,
+            vec![
+                (
+                    PathBuf::from("story/main.fater"),
+                    "START:\nbegin\n-> JUMP\n---\ninclude \"fly.fater\"\n".to_owned()
+                ),
+                (
+                    PathBuf::from("story/fly.fater"),
+                    "JUMP:\nyou can't\n-> END\n---\n".to_owned()
+                ),
+            ]
+        );
+
+        loader.insert("story/main.fater", "include \"missing.fater\"\n".to_owned());
+        assert!(matches!(
+            super::rename_section_in_files(
+                Path::new("story/main.fater"),
+                "FLY",
+                "JUMP",
+                &mut loader
+            ),
+            Err(RenameError::ReadFailed(..))
+        ));
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use crate::parse::{Choice, Outcome, Paragraph, Section, SectionIdentifier, Story};
+
+/// someone playing through a story: where they are, where they've been, and
+/// what happens when they make a choice
+#[derive(Clone, Debug)]
+pub struct Session<'a> {
+    story: &'a Story,
+    position: Position,
+    // for random choices
+    rng: Rng,
+    // how many times each section's been got to, this playthrough
+    visits: HashMap<SectionIdentifier, usize>,
+    // the once-only choices that have been taken, by their section and where
+    // they are in it
+    taken: HashSet<(SectionIdentifier, usize)>,
+}
+
+/// where in the story a session is
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Position {
+    /// the title screen, from which the story can be started
+    Menu,
+    Section(SectionIdentifier),
+}
+
+impl<'a> Session<'a> {
+    /// a session at the start section of the story. random choices always
+    /// turn out the same way, as if seeded with 0
+    pub fn new(story: &'a Story) -> Self {
+        Self::with_seed(story, 0)
+    }
+
+    /// a session at the start section of the story, whose random choices are
+    /// made from `seed`, so the same seed always plays out the same way
+    pub fn with_seed(story: &'a Story, seed: u64) -> Self {
+        let mut session = Self {
+            story,
+            position: Position::Menu,
+            rng: Rng::new(seed),
+            visits: HashMap::new(),
+            taken: HashSet::new(),
+        };
+        session.begin();
+        session
+    }
+
+    pub fn story(&self) -> &'a Story {
+        self.story
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    /// the section we're at, or None if we're at the menu
+    pub fn section(&self) -> Option<&'a Section> {
+        match &self.position {
+            Position::Menu => None,
+            Position::Section(identifier) => Some(&self.story.sections()[identifier]),
+        }
+    }
+
+    /// how many times the section's been got to this playthrough, including
+    /// being there now
+    pub fn visits(&self, identifier: &SectionIdentifier) -> usize {
+        self.visits.get(identifier).copied().unwrap_or(0)
+    }
+
+    /// the paragraphs of the section we're at that are shown this visit,
+    /// leaving out `[first]` ones after the first, and `[again]` ones on it
+    pub fn paragraphs(&self) -> Vec<&'a Paragraph> {
+        let Some(section) = self.section() else {
+            return Vec::new();
+        };
+        let visit = self.visits(section.identifier());
+
+        section
+            .description()
+            .paragraphs()
+            .iter()
+            .filter(|paragraph| paragraph.is_shown(visit))
+            .collect()
+    }
+
+    /// the choices that can be made from here, which are all of the section's
+    /// apart from once-only ones that have already been taken. the menu has
+    /// none --- use [`Session::begin`] to leave it
+    pub fn choices(&self) -> Vec<&'a Choice> {
+        self.available()
+            .into_iter()
+            .map(|(_, choice)| choice)
+            .collect()
+    }
+
+    // the choices that can be made, with where they are in the section
+    fn available(&self) -> Vec<(usize, &'a Choice)> {
+        let Some(section) = self.section() else {
+            return Vec::new();
+        };
+
+        section
+            .choices()
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !self.taken.contains(&(section.identifier().clone(), *i)))
+            .collect()
+    }
+
+    /// takes the choice at `index` in [`Session::choices`], returning the
+    /// new position
+    pub fn choose(&mut self, index: usize) -> Result<&Position, RuntimeError> {
+        let (i, choice) = self
+            .available()
+            .get(index)
+            .copied()
+            .ok_or(RuntimeError::NoSuchChoice(index))?;
+
+        if choice.is_once()
+            && let Some(section) = self.section()
+        {
+            self.taken.insert((section.identifier().clone(), i));
+        }
+
+        match choice.goto().to_string().as_str() {
+            "__RESTART" => self.begin(),
+            "__MENU" => self.position = Position::Menu,
+            _ => {
+                let goto = self.rng.outcome(choice.outcomes()).clone();
+                self.visit(goto);
+            }
+        }
+        Ok(&self.position)
+    }
+
+    /// makes the random choices from here on from `seed`
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// starts the story from the beginning, from wherever we are. it's a new
+    /// playthrough, so no sections have been visited, and every once-only
+    /// choice can be taken again
+    pub fn begin(&mut self) {
+        self.visits.clear();
+        self.taken.clear();
+        self.visit(self.story.metadata().start().clone());
+    }
+
+    fn visit(&mut self, identifier: SectionIdentifier) {
+        *self.visits.entry(identifier.clone()).or_default() += 1;
+        self.position = Position::Section(identifier);
+    }
+}
+
+/// a small, fast random number generator (splitmix64) that's the same
+/// everywhere, so a seed gives the same results whatever it runs on
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// somewhere below `n`
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// one of a choice's outcomes, each as likely as its chance
+    pub fn outcome<'b>(&mut self, outcomes: &'b [Outcome]) -> &'b SectionIdentifier {
+        let total: u64 = outcomes.iter().map(|outcome| outcome.chance() as u64).sum();
+        let mut roll = self.below(total);
+
+        for outcome in outcomes {
+            match roll.checked_sub(outcome.chance() as u64) {
+                Some(rest) => roll = rest,
+                None => return outcome.goto(),
+            }
+        }
+        unreachable!("the roll is below the total")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuntimeError {
+    NoSuchChoice(usize),
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSuchChoice(index) => write!(f, "there's no choice {}", index + 1),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session() {
+        let story = Story::parse(
+            "\
+START:
+Believe you can fly?
+yes -> FLY
+no -> START
+---
+FLY:
+You can't.
+-> END
+---",
+        )
+        .unwrap();
+        let start = || Position::Section(SectionIdentifier::parse((0, "START"), false).unwrap());
+
+        let mut session = Session::new(&story);
+        assert_eq!(session.position(), &start());
+        assert_eq!(session.choose(1), Ok(&start()));
+        assert_eq!(
+            session.choose(0),
+            Ok(&Position::Section(
+                SectionIdentifier::parse((0, "FLY"), false).unwrap()
+            ))
+        );
+        assert!(session.section().unwrap().is_ending());
+
+        // restart
+        assert_eq!(session.choose(0), Ok(&start()));
+        session.choose(0).unwrap();
+        // return to menu
+        assert_eq!(session.choose(1), Ok(&Position::Menu));
+        assert!(session.choices().is_empty());
+        assert_eq!(session.choose(0), Err(RuntimeError::NoSuchChoice(0)));
+
+        session.begin();
+        assert_eq!(session.position(), &start());
+    }
+
+    #[test]
+    fn random() {
+        let story = Story::parse(
+            "\
+START:
+Flip a coin.
+flip -> HEADS 25% | TAILS 75%
+---
+HEADS:
+Heads.
+-> START
+---
+TAILS:
+Tails.
+-> START
+---",
+        )
+        .unwrap();
+
+        let flips = |seed| {
+            let mut session = Session::with_seed(&story, seed);
+            (0..400)
+                .map(|_| {
+                    let position = session.choose(0).unwrap().clone();
+                    session.begin();
+                    position
+                })
+                .collect::<Vec<_>>()
+        };
+        let heads = Position::Section(SectionIdentifier::parse((0, "HEADS"), false).unwrap());
+
+        // the same seed always flips the same way
+        assert_eq!(flips(1), flips(1));
+        assert_ne!(flips(1), flips(2));
+        // and it comes up heads about a quarter of the time
+        let count = flips(1)
+            .iter()
+            .filter(|position| **position == heads)
+            .count();
+        assert!((70..130).contains(&count), "{}", count);
+    }
+
+    #[test]
+    fn visits() {
+        let story = Story::parse(
+            "\
+START:
+[first] You wake up.
+
+[again] You're back.
+
+The room is dark.
+* take the key -> START
+look around -> START
+---",
+        )
+        .unwrap();
+        let start = SectionIdentifier::parse((0, "START"), false).unwrap();
+        let texts = |session: &Session| {
+            session
+                .paragraphs()
+                .iter()
+                .map(|paragraph| paragraph.text().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let labels = |session: &Session| {
+            session
+                .choices()
+                .iter()
+                .map(|choice| choice.label())
+                .collect::<Vec<_>>()
+        };
+
+        let mut session = Session::new(&story);
+        assert_eq!(session.visits(&start), 1);
+        assert_eq!(texts(&session), ["You wake up.", "The room is dark."]);
+        assert_eq!(labels(&session), ["take the key", "look around"]);
+
+        session.choose(0).unwrap();
+        assert_eq!(session.visits(&start), 2);
+        assert_eq!(texts(&session), ["You're back.", "The room is dark."]);
+        // the key's been taken
+        assert_eq!(labels(&session), ["look around"]);
+        session.choose(0).unwrap();
+        assert_eq!(session.visits(&start), 3);
+        assert_eq!(session.choose(1), Err(RuntimeError::NoSuchChoice(1)));
+
+        // starting again forgets all of it
+        session.begin();
+        assert_eq!(session.visits(&start), 1);
+        assert_eq!(texts(&session), ["You wake up.", "The room is dark."]);
+        assert_eq!(labels(&session), ["take the key", "look around"]);
+    }
+}
@@ -0,0 +1,407 @@
+use std::{error::Error, fmt::Display};
+
+use crate::{
+    parse::{SectionIdentifier, Story},
+    runtime::{Position, Session},
+};
+
+/// a known route through a story, to check it still goes where it should.
+/// scripts are written like sections, one `key: value` step per line, and
+/// separated by `---`:
+///
+/// ```text
+/// test: the gravel eater
+/// choose: cat
+/// expect: CAT
+/// contains: A cat
+/// choose: 1
+/// expect: GRAVEL
+/// ---
+/// ```
+///
+/// choices can be given by their text, or by number, starting from 1, out of
+/// the ones still offered. `contains` only looks at the paragraphs shown on
+/// the visit it's at. random choices are made the same way every run, and
+/// `seed: 42` changes how they're made from there on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestScript {
+    name: String,
+    line_num: usize,
+    steps: Vec<(usize, Step)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Step {
+    Choose(ChoiceRef),
+    /// `__MENU` means being at the menu
+    Expect(SectionIdentifier),
+    Contains(String),
+    Seed(u64),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ChoiceRef {
+    /// starting from 0, normalized to 1 when writing scripts
+    Index(usize),
+    Label(String),
+}
+
+pub fn parse_scripts(str: &str) -> Result<Vec<TestScript>, ScriptError> {
+    let mut scripts: Vec<TestScript> = Vec::new();
+    let mut in_script = false;
+
+    for (num, line) in str.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        } else if line == "---" {
+            in_script = false;
+            continue;
+        }
+
+        let error = |error_type| ScriptError {
+            error_type,
+            line_num: num,
+        };
+
+        let (key, value) = line
+            .split_once(':')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .ok_or_else(|| error(ScriptErrorType::MissingColon))?;
+
+        if key == "test" {
+            scripts.push(TestScript {
+                name: value.to_owned(),
+                line_num: num,
+                steps: Vec::new(),
+            });
+            in_script = true;
+            continue;
+        }
+
+        let script = scripts
+            .last_mut()
+            .filter(|_| in_script)
+            .ok_or_else(|| error(ScriptErrorType::MissingTest))?;
+
+        let step = match key {
+            "choose" => Step::Choose(match value.parse::<usize>() {
+                Ok(0) => return Err(error(ScriptErrorType::ZeroChoice)),
+                Ok(index) => ChoiceRef::Index(index - 1),
+                Err(_) => ChoiceRef::Label(value.to_owned()),
+            }),
+            "expect" => Step::Expect(
+                SectionIdentifier::parse((num, value), false)
+                    .map_err(|_| error(ScriptErrorType::InvalidIdentifier))?,
+            ),
+            "contains" => Step::Contains(value.to_owned()),
+            "seed" => Step::Seed(
+                value
+                    .parse()
+                    .map_err(|_| error(ScriptErrorType::InvalidSeed))?,
+            ),
+            _ => return Err(error(ScriptErrorType::UnknownStep(key.to_owned()))),
+        };
+        script.steps.push((num, step));
+    }
+
+    Ok(scripts)
+}
+
+impl TestScript {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn line_num(&self) -> usize {
+        self.line_num
+    }
+
+    /// plays through the story from the start, following the script, and
+    /// stopping at the first step that doesn't go as expected
+    pub fn run(&self, story: &Story) -> Result<(), Divergence> {
+        let mut session = Session::new(story);
+
+        for (line_num, step) in &self.steps {
+            let diverge = |message| {
+                Err(Divergence {
+                    line_num: *line_num,
+                    message,
+                })
+            };
+
+            match step {
+                Step::Choose(choice) => {
+                    let index = match choice {
+                        ChoiceRef::Index(index) => Some(*index),
+                        ChoiceRef::Label(label) => session
+                            .choices()
+                            .iter()
+                            .position(|choice| choice.label() == *label),
+                    };
+
+                    let Some(index) = index.filter(|index| *index < session.choices().len()) else {
+                        let choices: Vec<String> = session
+                            .choices()
+                            .iter()
+                            .map(|choice| format!("\"{}\"", choice.label()))
+                            .collect();
+                        return diverge(format!(
+                            "no choice {} at {}, the choices are {}",
+                            choice,
+                            position_name(session.position()),
+                            if choices.is_empty() {
+                                "none".to_owned()
+                            } else {
+                                choices.join(", ")
+                            }
+                        ));
+                    };
+                    session.choose(index).expect("checked the choice exists");
+                }
+                Step::Expect(identifier) => {
+                    let matches = match session.position() {
+                        Position::Menu => identifier.to_string() == "__MENU",
+                        Position::Section(current) => current == identifier,
+                    };
+
+                    if !matches {
+                        return diverge(format!(
+                            "expected to be at {}, but at {}",
+                            identifier,
+                            position_name(session.position())
+                        ));
+                    }
+                }
+                Step::Contains(text) => {
+                    // only what's shown this visit
+                    let paragraphs: Vec<&str> = session
+                        .paragraphs()
+                        .iter()
+                        .map(|paragraph| paragraph.text())
+                        .collect();
+                    let description = paragraphs.join("\n\n");
+
+                    if !description.contains(text.as_str()) {
+                        return diverge(format!(
+                            "expected {} to contain \"{}\"",
+                            position_name(session.position()),
+                            text
+                        ));
+                    }
+                }
+                Step::Seed(seed) => session.seed(*seed),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn position_name(position: &Position) -> String {
+    match position {
+        Position::Menu => "the menu".to_owned(),
+        Position::Section(identifier) => identifier.to_string(),
+    }
+}
+
+impl Display for ChoiceRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "{}", index + 1),
+            Self::Label(label) => write!(f, "\"{}\"", label),
+        }
+    }
+}
+
+/// the first step of a script that didn't go as expected
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// starting from 0, normalized to 1 when rendering
+    line_num: usize,
+    message: String,
+}
+
+impl Divergence {
+    pub fn line_num(&self) -> usize {
+        self.line_num
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line_num + 1, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct ScriptError {
+    error_type: ScriptErrorType,
+    /// starting from 0, normalized to 1 when rendering
+    line_num: usize,
+}
+
+impl ScriptError {
+    pub fn error_type(&self) -> &ScriptErrorType {
+        &self.error_type
+    }
+
+    pub fn line_num(&self) -> usize {
+        self.line_num
+    }
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line_num + 1, self.error_type)
+    }
+}
+
+impl Error for ScriptError {}
+
+#[derive(Debug)]
+pub enum ScriptErrorType {
+    MissingColon,
+    MissingTest,
+    UnknownStep(String),
+    ZeroChoice,
+    InvalidIdentifier,
+    InvalidSeed,
+}
+
+impl Display for ScriptErrorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingColon => write!(f, "expected `step: value`"),
+            Self::MissingTest => write!(f, "steps must come after a `test: name` line"),
+            Self::UnknownStep(step) => write!(
+                f,
+                "unknown step `{}`, expected choose, expect, contains, or seed",
+                step
+            ),
+            Self::ZeroChoice => write!(f, "choices are numbered from 1"),
+            Self::InvalidIdentifier => write!(f, "expected a section identifier"),
+            Self::InvalidSeed => write!(f, "expected a seed, as a whole number"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STORY: &str = "\
+START:
+Believe you can fly?
+yes -> FLY
+no -> CLIFF
+---
+CLIFF:
+You arrive at the cliffside.
+jump -> FLY
+go home -> START
+---
+FLY:
+You can't.
+-> END
+---";
+
+    #[test]
+    fn run() {
+        let story = Story::parse(STORY).unwrap();
+        let scripts = parse_scripts(
+            "\
+# the long way round
+test: cliff
+expect: START
+choose: no
+expect: CLIFF
+contains: cliffside
+choose: 1
+expect: FLY
+choose: Return to menu
+expect: __MENU
+---
+test: wrong turn
+choose: 2
+expect: FLY
+---",
+        )
+        .unwrap();
+
+        assert_eq!(scripts.len(), 2);
+        assert_eq!(scripts[0].name(), "cliff");
+        assert_eq!(scripts[0].run(&story), Ok(()));
+
+        let divergence = scripts[1].run(&story).unwrap_err();
+        assert_eq!(divergence.line_num(), 13);
+        assert_eq!(divergence.message(), "expected to be at FLY, but at CLIFF");
+    }
+
+    #[test]
+    fn seed() {
+        let story = Story::parse(
+            "\
+START:
+Flip a coin.
+flip -> HEADS 50% | TAILS 50%
+---
+HEADS:
+Heads.
+-> START
+---
+TAILS:
+Tails.
+-> START
+---",
+        )
+        .unwrap();
+
+        // which way it lands for each seed
+        let landed = |seed: u64| {
+            let script = format!("test: flip\nseed: {}\nchoose: flip\nexpect: HEADS", seed);
+            parse_scripts(&script).unwrap()[0].run(&story).is_ok()
+        };
+        assert_eq!(landed(1), landed(1));
+        assert!((0..20).any(landed));
+        assert!(!(0..20).all(landed));
+    }
+
+    #[test]
+    fn missing_choice() {
+        let story = Story::parse(STORY).unwrap();
+        let scripts = parse_scripts("test: nope\nchoose: maybe").unwrap();
+
+        assert_eq!(
+            scripts[0].run(&story).unwrap_err().message(),
+            "no choice \"maybe\" at START, the choices are \"yes\", \"no\""
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            parse_scripts("choose: 1").unwrap_err().error_type,
+            ScriptErrorType::MissingTest
+        ));
+        assert!(matches!(
+            parse_scripts("test: foo\nchoose: 0")
+                .unwrap_err()
+                .error_type,
+            ScriptErrorType::ZeroChoice
+        ));
+        assert!(matches!(
+            parse_scripts("test: foo\njump: 1").unwrap_err().error_type,
+            ScriptErrorType::UnknownStep(_)
+        ));
+        assert!(matches!(
+            parse_scripts("test: foo\nseed: -1").unwrap_err().error_type,
+            ScriptErrorType::InvalidSeed
+        ));
+    }
+}
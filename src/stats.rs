@@ -0,0 +1,354 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+};
+
+use serde::Serialize;
+
+use crate::parse::{Choice, SectionIdentifier, Story};
+
+// how many steps finding the longest paths can take, since it has to try
+// every path, and there are far too many of them in a tangled story
+const LONGEST_PATHS_BUDGET: usize = 100_000;
+
+/// numbers about a story: how big it is, and how long it takes to finish
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Stats {
+    sections: usize,
+    /// choices between sections, not counting restarting or returning to
+    /// the menu
+    choices: usize,
+    endings: usize,
+    words: usize,
+    /// the average number of choices in a section that isn't an ending
+    branching_factor: f64,
+    section_words: Vec<SectionWords>,
+    ending_paths: Vec<EndingPaths>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SectionWords {
+    section: SectionIdentifier,
+    words: usize,
+}
+
+/// how many choices it takes to get from the start to an ending, without
+/// visiting any section twice. both are None if the ending can't be reached,
+/// and the longest is None too if there were too many paths to try them all
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EndingPaths {
+    ending: SectionIdentifier,
+    shortest: Option<usize>,
+    longest: Option<usize>,
+}
+
+impl Story {
+    pub fn stats(&self) -> Stats {
+        let identifiers: Vec<&SectionIdentifier> = self.sections().keys().collect();
+
+        let choices = self
+            .sections()
+            .values()
+            .map(|section| {
+                section
+                    .choices()
+                    .iter()
+                    .filter(|choice| !choice.is_special())
+                    .count()
+            })
+            .sum();
+
+        let endings: Vec<&SectionIdentifier> = identifiers
+            .iter()
+            .copied()
+            .filter(|identifier| self.sections()[*identifier].is_ending())
+            .collect();
+
+        let section_words: Vec<SectionWords> = identifiers
+            .iter()
+            .map(|identifier| SectionWords {
+                section: (*identifier).clone(),
+                words: self.sections()[*identifier]
+                    .description()
+                    .paragraphs()
+                    .iter()
+                    .map(|paragraph| paragraph.text().split_whitespace().count())
+                    .sum(),
+            })
+            .collect();
+
+        let branching = self.sections().len() - endings.len();
+        let branching_factor = if branching == 0 {
+            0.0
+        } else {
+            choices as f64 / branching as f64
+        };
+
+        let shortest = self.shortest_paths();
+        let longest = self.longest_paths();
+        let ending_paths = endings
+            .iter()
+            .map(|ending| EndingPaths {
+                ending: (*ending).clone(),
+                shortest: shortest.get(ending).copied(),
+                longest: longest
+                    .as_ref()
+                    .and_then(|longest| longest.get(ending).copied()),
+            })
+            .collect();
+
+        Stats {
+            sections: self.sections().len(),
+            choices,
+            endings: endings.len(),
+            words: section_words.iter().map(|section| section.words).sum(),
+            branching_factor,
+            section_words,
+            ending_paths,
+        }
+    }
+
+    // the sections a section's choices go to, other than restarting and
+    // returning to the menu
+    fn gotos<'a>(
+        &'a self,
+        identifier: &SectionIdentifier,
+    ) -> impl Iterator<Item = &'a SectionIdentifier> {
+        self.sections()[identifier]
+            .choices()
+            .iter()
+            .filter(|choice| !choice.is_special())
+            .flat_map(Choice::gotos)
+    }
+
+    // the fewest choices to get to every reachable section, breadth-first
+    fn shortest_paths(&self) -> HashMap<&SectionIdentifier, usize> {
+        let mut distances = HashMap::new();
+        let mut queue = VecDeque::from([(self.metadata().start(), 0)]);
+
+        while let Some((identifier, distance)) = queue.pop_front() {
+            if distances.contains_key(identifier) {
+                continue;
+            }
+            distances.insert(identifier, distance);
+            queue.extend(self.gotos(identifier).map(|goto| (goto, distance + 1)));
+        }
+
+        distances
+    }
+
+    // the most choices to get to every reachable section, without going
+    // through any section twice. this means trying every path, which is
+    // exponential, so it gives up with None after the budget's used up
+    fn longest_paths(&self) -> Option<HashMap<&SectionIdentifier, usize>> {
+        // false once it's run out of steps
+        fn walk<'a>(
+            story: &'a Story,
+            identifier: &'a SectionIdentifier,
+            visited: &mut HashSet<&'a SectionIdentifier>,
+            longest: &mut HashMap<&'a SectionIdentifier, usize>,
+            budget: &mut usize,
+        ) -> bool {
+            let Some(left) = budget.checked_sub(1) else {
+                return false;
+            };
+            *budget = left;
+
+            let distance = visited.len();
+            let entry = longest.entry(identifier).or_default();
+            *entry = (*entry).max(distance);
+
+            visited.insert(identifier);
+            for goto in story.gotos(identifier) {
+                if !visited.contains(goto) && !walk(story, goto, visited, longest, budget) {
+                    return false;
+                }
+            }
+            visited.remove(identifier);
+            true
+        }
+
+        let mut longest = HashMap::new();
+        let mut budget = LONGEST_PATHS_BUDGET;
+        walk(
+            self,
+            self.metadata().start(),
+            &mut HashSet::new(),
+            &mut longest,
+            &mut budget,
+        )
+        .then_some(longest)
+    }
+}
+
+impl Stats {
+    pub fn sections(&self) -> usize {
+        self.sections
+    }
+
+    pub fn choices(&self) -> usize {
+        self.choices
+    }
+
+    pub fn endings(&self) -> usize {
+        self.endings
+    }
+
+    pub fn words(&self) -> usize {
+        self.words
+    }
+
+    pub fn branching_factor(&self) -> f64 {
+        self.branching_factor
+    }
+
+    pub fn section_words(&self) -> &[SectionWords] {
+        &self.section_words
+    }
+
+    pub fn ending_paths(&self) -> &[EndingPaths] {
+        &self.ending_paths
+    }
+}
+
+impl SectionWords {
+    pub fn section(&self) -> &SectionIdentifier {
+        &self.section
+    }
+
+    pub fn words(&self) -> usize {
+        self.words
+    }
+}
+
+impl EndingPaths {
+    pub fn ending(&self) -> &SectionIdentifier {
+        &self.ending
+    }
+
+    pub fn shortest(&self) -> Option<usize> {
+        self.shortest
+    }
+
+    pub fn longest(&self) -> Option<usize> {
+        self.longest
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "sections: {}", self.sections)?;
+        writeln!(f, "choices: {}", self.choices)?;
+        writeln!(f, "endings: {}", self.endings)?;
+        writeln!(f, "words: {}", self.words)?;
+        writeln!(f, "average branching factor: {:.2}", self.branching_factor)?;
+
+        writeln!(f, "\npaths to endings:")?;
+        for paths in &self.ending_paths {
+            match (paths.shortest, paths.longest) {
+                (Some(shortest), Some(longest)) => writeln!(
+                    f,
+                    "    {}: shortest {}, longest {}",
+                    paths.ending, shortest, longest
+                )?,
+                (Some(shortest), None) => writeln!(
+                    f,
+                    "    {}: shortest {}, longest unknown",
+                    paths.ending, shortest
+                )?,
+                _ => writeln!(f, "    {}: unreachable", paths.ending)?,
+            }
+        }
+
+        writeln!(f, "\nwords per section:")?;
+        for section in &self.section_words {
+            writeln!(f, "    {}: {}", section.section, section.words)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats() {
+        let story = Story::parse(
+            "\
+START:
+Believe you can fly?
+yes -> FLY
+no -> CLIFF
+---
+CLIFF:
+You arrive at the cliffside.
+jump -> FLY
+go home -> START
+---
+FLY:
+You can't.
+-> END
+---
+ORPHAN:
+nobody comes here
+-> END
+---",
+        )
+        .unwrap();
+
+        let stats = story.stats();
+        assert_eq!(stats.sections(), 4);
+        assert_eq!(stats.choices(), 4);
+        assert_eq!(stats.endings(), 2);
+        assert_eq!(stats.words(), 4 + 5 + 2 + 3);
+        assert_eq!(stats.branching_factor(), 2.0);
+
+        let fly = &stats.ending_paths()[0];
+        assert_eq!(fly.ending().to_string(), "FLY");
+        assert_eq!(fly.shortest(), Some(1));
+        // START -> CLIFF -> FLY
+        assert_eq!(fly.longest(), Some(2));
+
+        let orphan = &stats.ending_paths()[1];
+        assert_eq!(orphan.ending().to_string(), "ORPHAN");
+        assert_eq!(orphan.shortest(), None);
+
+        assert_eq!(
+            serde_json::to_value(&stats).unwrap()["section_words"][1],
+            serde_json::json!({ "section": "CLIFF", "words": 5 })
+        );
+    }
+
+    #[test]
+    fn tangled() {
+        // every section goes to every other one, so there are far too many
+        // paths to try
+        let names: Vec<String> = (0..20).map(|i| format!("S{}", i)).collect();
+        let mut source = String::new();
+        for name in &names {
+            let name = if name == "S0" { "START" } else { name };
+            source.push_str(&format!("{}:\nlost\nout -> OUT\n", name));
+            for other in names
+                .iter()
+                .filter(|other| *other != name && *other != "S0")
+            {
+                source.push_str(&format!("to {} -> {}\n", other, other));
+            }
+            source.push_str("---\n");
+        }
+        source.push_str("OUT:\nfree\n-> END\n---\n");
+        let story = Story::parse(&source).unwrap();
+
+        let stats = story.stats();
+        let out = &stats.ending_paths()[0];
+        assert_eq!(out.shortest(), Some(1));
+        assert_eq!(out.longest(), None);
+        assert!(
+            stats
+                .to_string()
+                .contains("OUT: shortest 1, longest unknown")
+        );
+    }
+}
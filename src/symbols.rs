@@ -0,0 +1,537 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    parse::{self, ParseError, SectionIdentifier},
+    syntax::{LineKind, NodeKind, Span, SyntaxTree, TokenKind},
+};
+
+/// where every section of a story is defined, and every choice that goes to
+/// it, down to the spans of the identifiers, for editors and refactoring.
+/// worked out from the syntax trees, so it works on stories that don't parse
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbols {
+    definitions: Vec<Definition>,
+    references: Vec<Reference>,
+    /// where `start:` would go, if the start section is START because the
+    /// metadata doesn't say: the story's file, and the start of the
+    /// metadata's closing `---`, if it has any
+    implicit_start: Option<(Option<PathBuf>, Option<usize>)>,
+}
+
+/// a section's `NAME:` header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Definition {
+    /// including the chapter, even if it came from `chapter NAME`
+    identifier: SectionIdentifier,
+    file: Option<PathBuf>,
+    /// starting from 0, normalized to 1 when rendering
+    line_num: usize,
+    /// the identifier, without the colon
+    span: Span,
+    /// the whole section, from the header to the closing `---`
+    section_span: Span,
+    /// set by `chapter NAME` earlier in the file
+    file_chapter: Option<String>,
+}
+
+/// the target of a `-> TARGET` choice, or the `start:` of the metadata
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reference {
+    /// resolved relative to the chapter it's in, or as written if it goes
+    /// nowhere
+    identifier: SectionIdentifier,
+    written: SectionIdentifier,
+    file: Option<PathBuf>,
+    /// starting from 0, normalized to 1 when rendering
+    line_num: usize,
+    span: Span,
+    /// the chapter of the section the choice is in
+    chapter: Option<String>,
+}
+
+/// a change to make to the text of a file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edit {
+    pub file: Option<PathBuf>,
+    pub span: Span,
+    pub text: String,
+}
+
+impl Symbols {
+    /// the symbols across several files, which should be a story and
+    /// everything it includes
+    pub fn new<'a>(files: impl IntoIterator<Item = (Option<&'a Path>, &'a SyntaxTree)>) -> Self {
+        let mut definitions = Vec::new();
+        // resolved once we know every definition
+        let mut unresolved = Vec::new();
+        let mut has_start = false;
+        let mut story_file = None;
+        let mut metadata_end = None;
+
+        for (i, (file, tree)) in files.into_iter().enumerate() {
+            if i == 0 {
+                story_file = Some(file.map(Path::to_path_buf));
+            }
+            let mut file_chapter: Option<SectionIdentifier> = None;
+
+            for node in tree.nodes() {
+                let Some((line_num, text)) = tree.parser_lines(node).next() else {
+                    continue;
+                };
+
+                match node.kind() {
+                    // only the story's own metadata counts, not that of
+                    // included files
+                    NodeKind::Metadata if i == 0 => {
+                        metadata_end = node
+                            .lines()
+                            .last()
+                            .filter(|line| line.kind() == LineKind::Separator)
+                            .filter(|_| node.lines().len() > 1)
+                            .map(|line| line.span().start);
+
+                        for line in node.lines() {
+                            let key = line.token(TokenKind::Key).map(|key| tree.text(key.span()));
+                            let value = line.token(TokenKind::Value);
+                            if key != Some("start") {
+                                continue;
+                            }
+                            has_start = true;
+
+                            if let Some(value) = value
+                                && let Ok(start) = SectionIdentifier::parse(
+                                    (line.line_num(), tree.text(value.span())),
+                                    false,
+                                )
+                            {
+                                unresolved.push(Reference {
+                                    identifier: start.clone(),
+                                    written: start,
+                                    file: file.map(Path::to_path_buf),
+                                    line_num: line.line_num(),
+                                    span: value.span(),
+                                    chapter: None,
+                                });
+                            }
+                        }
+                    }
+                    NodeKind::Chapter => {
+                        file_chapter = parse::parse_chapter(line_num, text).and_then(Result::ok);
+                    }
+                    NodeKind::Section => {
+                        let header = &node.lines()[0];
+                        let mut chapter = file_chapter.as_ref().map(|chapter| chapter.name());
+
+                        if let Some(token) = header.token(TokenKind::Identifier)
+                            && let Ok(identifier) =
+                                SectionIdentifier::parse((line_num, tree.text(token.span())), false)
+                        {
+                            let identifier = match (identifier.chapter(), chapter) {
+                                (None, Some(chapter)) => identifier.in_chapter(chapter),
+                                _ => identifier,
+                            };
+
+                            definitions.push(Definition {
+                                identifier,
+                                file: file.map(Path::to_path_buf),
+                                line_num,
+                                span: token.span(),
+                                section_span: node.span(),
+                                file_chapter: chapter.map(str::to_owned),
+                            });
+                            chapter = definitions.last().and_then(|def| def.identifier.chapter());
+                        }
+
+                        for line in node.lines() {
+                            if line.kind() != LineKind::Choice {
+                                continue;
+                            }
+                            // every outcome of a random choice is a reference
+                            for token in line.tokens() {
+                                if token.kind() != TokenKind::Identifier {
+                                    continue;
+                                }
+                                let Ok(goto) = SectionIdentifier::parse(
+                                    (line.line_num(), tree.text(token.span())),
+                                    false,
+                                ) else {
+                                    continue;
+                                };
+
+                                if !goto.is_reserved() {
+                                    unresolved.push(Reference {
+                                        identifier: goto.clone(),
+                                        written: goto,
+                                        file: file.map(Path::to_path_buf),
+                                        line_num: line.line_num(),
+                                        span: token.span(),
+                                        chapter: chapter.map(str::to_owned),
+                                    });
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let identifiers: HashSet<&SectionIdentifier> =
+            definitions.iter().map(|def| &def.identifier).collect();
+        let references = unresolved
+            .into_iter()
+            .map(|mut reference| {
+                if let Some(identifier) = reference
+                    .identifier
+                    .resolve(reference.chapter.as_deref(), |identifier| {
+                        identifiers.contains(identifier)
+                    })
+                {
+                    reference.identifier = identifier;
+                }
+                reference
+            })
+            .collect();
+
+        Self {
+            definitions,
+            references,
+            implicit_start: story_file
+                .filter(|_| !has_start)
+                .map(|file| (file, metadata_end)),
+        }
+    }
+
+    pub fn definitions(&self) -> &[Definition] {
+        &self.definitions
+    }
+
+    pub fn references(&self) -> &[Reference] {
+        &self.references
+    }
+
+    pub fn definition(&self, identifier: &SectionIdentifier) -> Option<&Definition> {
+        self.definitions
+            .iter()
+            .find(|def| def.identifier == *identifier)
+    }
+
+    /// every choice that goes to a section
+    pub fn references_to<'a>(
+        &'a self,
+        identifier: &'a SectionIdentifier,
+    ) -> impl Iterator<Item = &'a Reference> {
+        self.references
+            .iter()
+            .filter(move |reference| reference.identifier == *identifier)
+    }
+
+    /// the section whose identifier is at a byte offset in a file, either
+    /// where it's defined or where a choice goes to it
+    pub fn at(&self, file: Option<&Path>, offset: usize) -> Option<&SectionIdentifier> {
+        let definitions = self
+            .definitions
+            .iter()
+            .map(|def| (&def.identifier, def.file.as_deref(), def.span));
+        let references = self.references.iter().map(|reference| {
+            (
+                &reference.identifier,
+                reference.file.as_deref(),
+                reference.span,
+            )
+        });
+
+        definitions
+            .chain(references)
+            .find(|(_, def_file, span)| *def_file == file && span.contains(offset))
+            .map(|(identifier, _, _)| identifier)
+    }
+
+    /// the edits that rename a section, and every choice that goes to it.
+    /// identifiers are written without their chapter wherever they can be,
+    /// and the rename is refused if it would change where any choice goes.
+    /// renaming START when it's only the start by name adds `start:` to the
+    /// metadata
+    pub fn rename(
+        &self,
+        old: &SectionIdentifier,
+        new: &SectionIdentifier,
+    ) -> Result<Vec<Edit>, RenameError> {
+        let definition = self.definition(old).ok_or(RenameError::NoSuchSection)?;
+        if new.is_reserved() {
+            return Err(RenameError::Reserved);
+        } else if new == old {
+            return Ok(Vec::new());
+        } else if self.definition(new).is_some() {
+            return Err(RenameError::AlreadyExists);
+        }
+
+        let identifiers: HashSet<&SectionIdentifier> = self
+            .definitions
+            .iter()
+            .map(|def| {
+                if def.identifier == *old {
+                    new
+                } else {
+                    &def.identifier
+                }
+            })
+            .collect();
+        let resolve = |written: &SectionIdentifier, chapter: Option<&str>| {
+            written.resolve(chapter, |identifier| identifiers.contains(identifier))
+        };
+        let short = SectionIdentifier::parse((0, new.name()), false).expect("a valid name");
+
+        // a header gets put in the file's chapter, unless it has its own
+        let header = match &definition.file_chapter {
+            Some(chapter) if short.in_chapter(chapter) == *new => short.clone(),
+            None if new.chapter().is_none() => short.clone(),
+            _ if new.chapter().is_some() => new.clone(),
+            _ => {
+                return Err(RenameError::Unwritable(
+                    definition.file.clone(),
+                    definition.line_num,
+                ));
+            }
+        };
+        let mut edits = vec![Edit {
+            file: definition.file.clone(),
+            span: definition.span,
+            text: header.to_string(),
+        }];
+
+        for reference in &self.references {
+            let chapter = reference.chapter.as_deref();
+            let unwritable = || RenameError::Unwritable(reference.file.clone(), reference.line_num);
+
+            if reference.identifier == *old {
+                let written = [&short, new]
+                    .into_iter()
+                    .find(|written| resolve(written, chapter).as_ref() == Some(new))
+                    .ok_or_else(unwritable)?;
+
+                edits.push(Edit {
+                    file: reference.file.clone(),
+                    span: reference.span,
+                    text: written.to_string(),
+                });
+            } else if self.definition(&reference.identifier).is_some()
+                && resolve(&reference.written, chapter).as_ref() != Some(&reference.identifier)
+            {
+                // it can't be allowed to end up somewhere else
+                return Err(unwritable());
+            }
+        }
+
+        // the story has to keep starting in the same place, so it says so
+        if let Some((file, metadata_end)) = &self.implicit_start
+            && old.to_string() == "START"
+        {
+            let (offset, text) = match metadata_end {
+                Some(offset) => (*offset, format!("start: {}\n", new)),
+                None => (0, format!("---\nstart: {}\n---\n\n", new)),
+            };
+            edits.push(Edit {
+                file: file.clone(),
+                span: Span {
+                    start: offset,
+                    end: offset,
+                },
+                text,
+            });
+        }
+
+        Ok(edits)
+    }
+}
+
+impl Definition {
+    pub fn identifier(&self) -> &SectionIdentifier {
+        &self.identifier
+    }
+
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    pub fn line_num(&self) -> usize {
+        self.line_num
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn section_span(&self) -> Span {
+        self.section_span
+    }
+}
+
+impl Reference {
+    pub fn identifier(&self) -> &SectionIdentifier {
+        &self.identifier
+    }
+
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    pub fn line_num(&self) -> usize {
+        self.line_num
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[derive(Debug)]
+pub enum RenameError {
+    /// the old or new identifier isn't one
+    InvalidIdentifier(ParseError),
+    /// the story, or a file it includes
+    ReadFailed(PathBuf, io::Error),
+    NoSuchSection,
+    Reserved,
+    AlreadyExists,
+    /// the new identifier can't be written somewhere without it meaning
+    /// something else, at this file and line
+    Unwritable(Option<PathBuf>, usize),
+}
+
+impl Display for RenameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidIdentifier(err) => write!(f, "{}", err.error_type()),
+            Self::ReadFailed(path, err) => {
+                write!(f, "couldn't read {}: {}", path.display(), err)
+            }
+            Self::NoSuchSection => write!(f, "there's no section with that identifier"),
+            Self::Reserved => write!(f, "that identifier is reserved"),
+            Self::AlreadyExists => write!(f, "there's already a section with that identifier"),
+            Self::Unwritable(file, line_num) => {
+                write!(f, "the new identifier would mean something else at ")?;
+                match file {
+                    Some(file) => write!(f, "{}:{}", file.display(), line_num + 1),
+                    None => write!(f, "line {}", line_num + 1),
+                }
+            }
+        }
+    }
+}
+
+impl Error for RenameError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STORY: &str = "\
+START:
+Believe you can fly?
+yes -> CH1::FLY
+no -> START
+---
+chapter CH1
+FLY:
+You can't.
+again -> FLY
+out -> START
+flip -> FLY 50% | START 50%
+---
+";
+
+    fn identifier(str: &str) -> SectionIdentifier {
+        SectionIdentifier::parse((0, str), false).unwrap()
+    }
+
+    #[test]
+    fn symbols() {
+        let tree = SyntaxTree::parse(STORY);
+        let symbols = Symbols::new([(None, &tree)]);
+
+        let fly = identifier("CH1::FLY");
+        let definition = symbols.definition(&fly).unwrap();
+        assert_eq!(definition.line_num(), 6);
+        assert_eq!(tree.text(definition.span()), "FLY");
+
+        // both the qualified goto, and the one relative to the chapter
+        let references: Vec<usize> = symbols
+            .references_to(&fly)
+            .map(Reference::line_num)
+            .collect();
+        assert_eq!(references, vec![2, 8, 10]);
+
+        let offset = STORY.find("again -> FLY").unwrap() + 10;
+        assert_eq!(symbols.at(None, offset), Some(&fly));
+        assert_eq!(symbols.at(None, 0), Some(&identifier("START")));
+        assert_eq!(symbols.at(None, 8), None);
+    }
+
+    #[test]
+    fn rename() {
+        let tree = SyntaxTree::parse(STORY);
+        let symbols = Symbols::new([(None, &tree)]);
+
+        let edits = symbols
+            .rename(&identifier("CH1::FLY"), &identifier("CH1::JUMP"))
+            .unwrap();
+        let renamed = tree.edit(edits.into_iter().map(|edit| (edit.span, edit.text)));
+        assert_eq!(renamed, STORY.replace("FLY", "JUMP"));
+
+        // without `start:`, the story says where it starts now
+        let edits = symbols
+            .rename(&identifier("START"), &identifier("BEGIN"))
+            .unwrap();
+        let renamed = tree.edit(edits.into_iter().map(|edit| (edit.span, edit.text)));
+        assert_eq!(
+            renamed,
+            format!(
+                "---\nstart: BEGIN\n---\n\n{}",
+                STORY.replace("START", "BEGIN")
+            )
+        );
+        let tree = SyntaxTree::parse(&format!("---\ntitle: Cliff\n---\n{}", STORY));
+        let edits = Symbols::new([(None, &tree)])
+            .rename(&identifier("START"), &identifier("BEGIN"))
+            .unwrap();
+        let renamed = tree.edit(edits.into_iter().map(|edit| (edit.span, edit.text)));
+        assert_eq!(
+            renamed,
+            format!(
+                "---\ntitle: Cliff\nstart: BEGIN\n---\n{}",
+                STORY.replace("START", "BEGIN")
+            )
+        );
+
+        let tree = SyntaxTree::parse(&format!("---\nstart: START\n---\n{}", STORY));
+        let edits = Symbols::new([(None, &tree)])
+            .rename(&identifier("START"), &identifier("BEGIN"))
+            .unwrap();
+        assert_eq!(edits.len(), 5);
+
+        assert!(matches!(
+            symbols.rename(&identifier("START"), &identifier("CH1::FLY")),
+            Err(RenameError::AlreadyExists)
+        ));
+        assert!(matches!(
+            symbols.rename(&identifier("START"), &identifier("END")),
+            Err(RenameError::Reserved)
+        ));
+        assert!(matches!(
+            symbols.rename(&identifier("NOWHERE"), &identifier("START")),
+            Err(RenameError::NoSuchSection)
+        ));
+        // it's after `chapter CH1`, so it can't be taken out of it
+        assert!(matches!(
+            symbols.rename(&identifier("CH1::FLY"), &identifier("FLY")),
+            Err(RenameError::Unwritable(None, 6))
+        ));
+    }
+}
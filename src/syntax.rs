@@ -0,0 +1,574 @@
+use std::fmt::Display;
+
+use crate::parse::Choice;
+
+/// a story exactly as it was written, comments, blank lines, wrapping and all,
+/// so that tools can change part of it and write it back without messing up
+/// the rest. [`Story`](crate::parse::Story)s are derived from these.
+///
+/// building the tree never fails --- lines are sorted into nodes the same way
+/// the parser would, and it's only when deriving the story that anything gets
+/// checked
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxTree {
+    source: String,
+    nodes: Vec<Node>,
+}
+
+/// a top-level part of a story, made up of whole lines
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    kind: NodeKind,
+    lines: Vec<Line>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    /// a blank line or a comment between everything else
+    Trivia,
+    /// the `---` fenced header
+    Metadata,
+    Include,
+    Chapter,
+    /// from the `NAME:` header to the closing `---`, including any comments
+    /// and blank lines in between
+    Section,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    kind: LineKind,
+    /// starting from 0, normalized to 1 when rendering
+    line_num: usize,
+    /// including the line ending, if there is one
+    span: Span,
+    /// covering the whole line, with nothing left out
+    tokens: Vec<Token>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineKind {
+    Blank,
+    Comment,
+    Separator,
+    MetadataEntry,
+    Include,
+    Chapter,
+    Header,
+    Description,
+    Choice,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Whitespace,
+    Newline,
+    Comment,
+    Separator,
+    /// the key of a metadata entry
+    Key,
+    Value,
+    Colon,
+    /// `include` or `chapter`
+    Keyword,
+    /// the quoted path of an include, including the quotes
+    Path,
+    Identifier,
+    /// description text, of a section or a choice
+    Text,
+    Arrow,
+    /// the chance of an outcome of a random choice, like `50%`
+    Chance,
+    /// `|`, between the outcomes of a random choice
+    Bar,
+    /// `*` before a once-only choice, or `+` before a sticky one
+    Marker,
+}
+
+/// a range of bytes in the source
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl SyntaxTree {
+    pub fn parse(source: &str) -> Self {
+        let mut lines = source
+            .split_inclusive('\n')
+            .enumerate()
+            .scan(0, |offset, (line_num, text)| {
+                let start = *offset;
+                *offset += text.len();
+                Some((line_num, start, text))
+            })
+            .peekable();
+
+        let mut nodes = Vec::new();
+        // the header can only come before everything else
+        let mut first = true;
+
+        while let Some((line_num, start, text)) = lines.next() {
+            let content = strip_newline(text).trim();
+            let line = |kind| Line::new(kind, line_num, start, text);
+
+            if is_comment(text) {
+                nodes.push(Node::new(NodeKind::Trivia, vec![line(LineKind::Comment)]));
+                continue;
+            } else if content.is_empty() {
+                nodes.push(Node::new(NodeKind::Trivia, vec![line(LineKind::Blank)]));
+                continue;
+            }
+
+            if first && content == "---" {
+                let mut node = Node::new(NodeKind::Metadata, vec![line(LineKind::Separator)]);
+                for (line_num, start, text) in lines.by_ref() {
+                    let content = strip_newline(text).trim();
+                    let kind = if is_comment(text) {
+                        LineKind::Comment
+                    } else if content.is_empty() {
+                        LineKind::Blank
+                    } else if content == "---" {
+                        LineKind::Separator
+                    } else {
+                        LineKind::MetadataEntry
+                    };
+
+                    node.lines.push(Line::new(kind, line_num, start, text));
+                    if kind == LineKind::Separator {
+                        break;
+                    }
+                }
+                nodes.push(node);
+            } else if is_keyword("include", content) {
+                nodes.push(Node::new(NodeKind::Include, vec![line(LineKind::Include)]));
+            } else if is_keyword("chapter", content) {
+                nodes.push(Node::new(NodeKind::Chapter, vec![line(LineKind::Chapter)]));
+            } else {
+                let mut node = Node::new(NodeKind::Section, vec![line(LineKind::Header)]);
+                for (line_num, start, text) in lines.by_ref() {
+                    let content = strip_newline(text).trim();
+                    let kind = if is_comment(text) {
+                        LineKind::Comment
+                    } else if content == "---" {
+                        LineKind::Separator
+                    } else if content.is_empty() {
+                        LineKind::Blank
+                    } else if Choice::parse(content, line_num).is_ok() {
+                        LineKind::Choice
+                    } else {
+                        // after the choices, this is an error, but that's for
+                        // the parser to find
+                        LineKind::Description
+                    };
+
+                    node.lines.push(Line::new(kind, line_num, start, text));
+                    if kind == LineKind::Separator {
+                        break;
+                    }
+                }
+                nodes.push(node);
+            }
+            first = false;
+        }
+
+        Self {
+            source: source.to_owned(),
+            nodes,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn text(&self, span: Span) -> &str {
+        &self.source[span.start..span.end]
+    }
+
+    /// the text of a line, without its line ending
+    pub fn line_text(&self, line: &Line) -> &str {
+        strip_newline(self.text(line.span))
+    }
+
+    /// the lines of a node the way the parser sees them: numbered, and
+    /// without comments or line endings
+    pub fn parser_lines<'a>(&'a self, node: &'a Node) -> impl Iterator<Item = (usize, &'a str)> {
+        node.lines
+            .iter()
+            .filter(|line| line.kind != LineKind::Comment)
+            .map(|line| (line.line_num, self.line_text(line)))
+    }
+
+    /// the source with each span replaced by its text, leaving everything else
+    /// exactly as it was. the spans can't overlap
+    pub fn edit(&self, edits: impl IntoIterator<Item = (Span, String)>) -> String {
+        let mut edits: Vec<(Span, String)> = edits.into_iter().collect();
+        edits.sort_by_key(|(span, _)| *span);
+
+        let mut edited = String::with_capacity(self.source.len());
+        let mut offset = 0;
+        for (span, text) in edits {
+            assert!(span.start >= offset, "edits can't overlap");
+            edited.push_str(&self.source[offset..span.start]);
+            edited.push_str(&text);
+            offset = span.end;
+        }
+        edited.push_str(&self.source[offset..]);
+
+        edited
+    }
+}
+
+impl Display for SyntaxTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Node {
+    fn new(kind: NodeKind, lines: Vec<Line>) -> Self {
+        Self { kind, lines }
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.lines.first().map_or(0, |line| line.span.start),
+            end: self.lines.last().map_or(0, |line| line.span.end),
+        }
+    }
+}
+
+impl Line {
+    fn new(kind: LineKind, line_num: usize, start: usize, text: &str) -> Self {
+        Self {
+            kind,
+            line_num,
+            span: Span {
+                start,
+                end: start + text.len(),
+            },
+            tokens: tokenize(kind, start, text),
+        }
+    }
+
+    pub fn kind(&self) -> LineKind {
+        self.kind
+    }
+
+    pub fn line_num(&self) -> usize {
+        self.line_num
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// the first token of a kind on the line
+    pub fn token(&self, kind: TokenKind) -> Option<&Token> {
+        self.tokens.iter().find(|token| token.kind == kind)
+    }
+}
+
+impl Token {
+    pub fn kind(&self) -> TokenKind {
+        self.kind
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl Span {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset <= self.end
+    }
+}
+
+// the same as the parser's idea of a comment, which has to start at the very
+// beginning of the line
+fn is_comment(line: &str) -> bool {
+    line.starts_with('#')
+}
+
+// `include ...` or `chapter ...`, which has to be followed by whitespace
+fn is_keyword(keyword: &str, content: &str) -> bool {
+    content
+        .strip_prefix(keyword)
+        .is_some_and(|rest| rest.starts_with(char::is_whitespace))
+}
+
+fn strip_newline(text: &str) -> &str {
+    text.strip_suffix('\n')
+        .map(|text| text.strip_suffix('\r').unwrap_or(text))
+        .unwrap_or(text)
+}
+
+// splits a line into tokens, based on what kind of line it is. every byte
+// ends up in exactly one token
+fn tokenize(kind: LineKind, start: usize, text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let content = strip_newline(text);
+
+    let mut push = |kind, offset: usize, str: &str| {
+        if !str.is_empty() {
+            tokens.push(Token {
+                kind,
+                span: Span {
+                    start: start + offset,
+                    end: start + offset + str.len(),
+                },
+            });
+        }
+    };
+
+    // pushes a token for the trimmed text, and whitespace tokens either side
+    let mut push_trimmed = |kind, offset: usize, str: &str| {
+        let trimmed_start = str.trim_start();
+        let leading = str.len() - trimmed_start.len();
+        let trimmed = trimmed_start.trim_end();
+
+        push(TokenKind::Whitespace, offset, &str[..leading]);
+        push(kind, offset + leading, trimmed);
+        push(
+            TokenKind::Whitespace,
+            offset + leading + trimmed.len(),
+            &trimmed_start[trimmed.len()..],
+        );
+    };
+
+    match kind {
+        LineKind::Blank => push_trimmed(TokenKind::Whitespace, 0, content),
+        LineKind::Comment => push_trimmed(TokenKind::Comment, 0, content),
+        LineKind::Separator => push_trimmed(TokenKind::Separator, 0, content),
+        LineKind::Description => push_trimmed(TokenKind::Text, 0, content),
+        LineKind::MetadataEntry => match content.split_once(':') {
+            Some((key, value)) => {
+                push_trimmed(TokenKind::Key, 0, key);
+                push_trimmed(TokenKind::Colon, key.len(), ":");
+                push_trimmed(TokenKind::Value, key.len() + 1, value);
+            }
+            None => push_trimmed(TokenKind::Text, 0, content),
+        },
+        LineKind::Header => match content.trim_end().strip_suffix(':') {
+            Some(identifier) => {
+                push_trimmed(TokenKind::Identifier, 0, identifier);
+                push_trimmed(
+                    TokenKind::Colon,
+                    identifier.len(),
+                    &content[identifier.len()..],
+                );
+            }
+            None => push_trimmed(TokenKind::Text, 0, content),
+        },
+        LineKind::Include | LineKind::Chapter => {
+            // `include` and `chapter` are the same length
+            let keyword_end = content.len() - content.trim_start().len() + "include".len();
+            let argument = if kind == LineKind::Include {
+                TokenKind::Path
+            } else {
+                TokenKind::Identifier
+            };
+
+            push_trimmed(TokenKind::Keyword, 0, &content[..keyword_end]);
+            push_trimmed(argument, keyword_end, &content[keyword_end..]);
+        }
+        LineKind::Choice => {
+            let (description, goto) = content.split_once("->").expect("choices have an arrow");
+
+            // the same as the parser: a `*` or `+` with whitespace after it
+            let marker_len = match description.trim_start().split_at_checked(1) {
+                Some(("*" | "+", rest)) if rest.starts_with(char::is_whitespace) => {
+                    description.len() - rest.len()
+                }
+                _ => 0,
+            };
+            let (marker, text) = description.split_at(marker_len);
+
+            push_trimmed(TokenKind::Marker, 0, marker);
+            push_trimmed(TokenKind::Text, marker_len, text);
+            push_trimmed(TokenKind::Arrow, description.len(), "->");
+
+            // a random choice has an identifier and a chance for each outcome
+            let mut offset = description.len() + 2;
+            for (i, outcome) in goto.split('|').enumerate() {
+                if i > 0 {
+                    push_trimmed(TokenKind::Bar, offset, "|");
+                    offset += 1;
+                }
+                match outcome.trim_end().rsplit_once(char::is_whitespace) {
+                    Some((identifier, chance)) if chance.ends_with('%') => {
+                        push_trimmed(TokenKind::Identifier, offset, identifier);
+                        push_trimmed(
+                            TokenKind::Chance,
+                            offset + identifier.len(),
+                            &outcome[identifier.len()..],
+                        );
+                    }
+                    _ => push_trimmed(TokenKind::Identifier, offset, outcome),
+                }
+                offset += outcome.len();
+            }
+        }
+    }
+
+    push(TokenKind::Newline, content.len(), &text[content.len()..]);
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+# a comment
+
+---
+title: The Cliff
+---
+START:
+You arrive at the cliffside.
+  Believe you can fly?
+
+yes -> CH1::FLY
+don't pull ->  NUCLEAR\r
+*  flip -> HEADS 50% |TAILS 50%
+---
+chapter CH1
+include \"other.fater\"
+FLY:
+You can't.
+-> END";
+
+    #[test]
+    fn lossless() {
+        let tree = SyntaxTree::parse(SOURCE);
+        assert_eq!(tree.to_string(), SOURCE);
+
+        // every byte is in exactly one token, in order
+        let mut offset = 0;
+        for node in tree.nodes() {
+            for line in node.lines() {
+                for token in line.tokens() {
+                    assert_eq!(token.span().start, offset);
+                    offset = token.span().end;
+                }
+            }
+        }
+        assert_eq!(offset, SOURCE.len());
+    }
+
+    #[test]
+    fn nodes() {
+        let tree = SyntaxTree::parse(SOURCE);
+
+        let kinds: Vec<NodeKind> = tree.nodes().iter().map(Node::kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                NodeKind::Trivia,
+                NodeKind::Trivia,
+                NodeKind::Metadata,
+                NodeKind::Section,
+                NodeKind::Chapter,
+                NodeKind::Include,
+                NodeKind::Section,
+            ]
+        );
+
+        let start = &tree.nodes()[3];
+        let kinds: Vec<LineKind> = start.lines().iter().map(Line::kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                LineKind::Header,
+                LineKind::Description,
+                LineKind::Description,
+                LineKind::Blank,
+                LineKind::Choice,
+                LineKind::Choice,
+                LineKind::Choice,
+                LineKind::Separator,
+            ]
+        );
+
+        let choice = &start.lines()[5];
+        let text = |kind| tree.text(choice.token(kind).unwrap().span());
+        assert_eq!(text(TokenKind::Text), "don't pull");
+        assert_eq!(text(TokenKind::Identifier), "NUCLEAR");
+        assert_eq!(text(TokenKind::Newline), "\r\n");
+        assert_eq!(tree.line_text(choice), "don't pull ->  NUCLEAR");
+
+        let random = &start.lines()[6];
+        let kinds: Vec<(TokenKind, &str)> = random
+            .tokens()
+            .iter()
+            .filter(|token| token.kind() != TokenKind::Whitespace)
+            .map(|token| (token.kind(), tree.text(token.span())))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (TokenKind::Marker, "*"),
+                (TokenKind::Text, "flip"),
+                (TokenKind::Arrow, "->"),
+                (TokenKind::Identifier, "HEADS"),
+                (TokenKind::Chance, "50%"),
+                (TokenKind::Bar, "|"),
+                (TokenKind::Identifier, "TAILS"),
+                (TokenKind::Chance, "50%"),
+                (TokenKind::Newline, "\n"),
+            ]
+        );
+
+        let include = &tree.nodes()[5].lines()[0];
+        assert_eq!(
+            tree.text(include.token(TokenKind::Path).unwrap().span()),
+            "\"other.fater\""
+        );
+    }
+
+    #[test]
+    fn edit() {
+        let tree = SyntaxTree::parse(SOURCE);
+        let header = &tree.nodes()[6].lines()[0];
+
+        let edited = tree.edit([(
+            header.token(TokenKind::Identifier).unwrap().span(),
+            "JUMP".to_owned(),
+        )]);
+        assert_eq!(edited, SOURCE.replace("FLY:", "JUMP:"));
+    }
+}
@@ -0,0 +1,755 @@
+use std::fmt::{Display, Write};
+
+use serde_json::{Value, json};
+
+use crate::{
+    export,
+    parse::{Choice, Metadata, Outcome, ParseResult, Section, SectionIdentifier, Shown, Story},
+};
+
+/// something that doesn't carry over between twee and a story, so was left
+/// out, or changed to fit
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    /// the line of the twee it's about, when importing
+    line_num: Option<usize>,
+    message: String,
+}
+
+impl Warning {
+    fn new(line_num: Option<usize>, message: String) -> Self {
+        Self { line_num, message }
+    }
+
+    pub fn line_num(&self) -> Option<usize> {
+        self.line_num
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(line_num) = self.line_num {
+            write!(f, "line {}: ", line_num + 1)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Story {
+    /// the story in twee 3, the text form of twine stories. each section is a
+    /// passage, with its choices as links, and endings are passages without
+    /// any
+    pub fn to_twee(&self) -> (String, Vec<Warning>) {
+        let mut twee = String::new();
+        let mut warnings = Vec::new();
+        let mut warn = |message| warnings.push(Warning::new(None, message));
+
+        let metadata = self.metadata();
+        if let Some(title) = metadata.title() {
+            writeln!(twee, ":: StoryTitle\n{}\n", title).unwrap();
+        }
+        for (key, value) in [
+            ("author", metadata.author()),
+            ("language", metadata.language()),
+            ("version", metadata.version()),
+            ("description", metadata.description()),
+        ] {
+            if value.is_some() {
+                warn(format!(
+                    "twee has nowhere to put the {}, so it's left out",
+                    key
+                ));
+            }
+        }
+
+        let data = json!({
+            "ifid": export::uuid(self),
+            "format": "Harlowe",
+            "format-version": "3.3.9",
+            "start": metadata.start().to_string(),
+            "zoom": 1,
+        });
+        writeln!(
+            twee,
+            ":: StoryData\n{}\n",
+            serde_json::to_string_pretty(&data).expect("it's made of json")
+        )
+        .unwrap();
+
+        for section in self.sections().values() {
+            let identifier = section.identifier();
+            writeln!(twee, ":: {}", identifier).unwrap();
+
+            let paragraphs: Vec<String> = section
+                .description()
+                .paragraphs()
+                .iter()
+                .map(|paragraph| match paragraph.shown() {
+                    Shown::Always => paragraph.text().to_owned(),
+                    // harlowe counts visits to a passage, including this one
+                    Shown::First => format!("(if: visits is 1)[{}]", paragraph.text()),
+                    Shown::Again => format!("(if: visits > 1)[{}]", paragraph.text()),
+                })
+                .collect();
+            let description = paragraphs.join("\n\n");
+            if description.contains("[[") {
+                warn(format!(
+                    "{} has `[[` in its description, which twine will take as a link",
+                    identifier
+                ));
+            }
+            writeln!(twee, "{}", description).unwrap();
+
+            if !section.is_ending() {
+                writeln!(twee).unwrap();
+                for choice in section.choices() {
+                    let label = choice.label();
+                    if choice.is_once() {
+                        warn(format!(
+                            "a choice in {} is once-only, which twine links can't be, so it's always offered",
+                            identifier
+                        ));
+                    }
+                    if label.contains("]]") {
+                        warn(format!(
+                            "a choice in {} has `]]` in it, which ends the link early",
+                            identifier
+                        ));
+                    }
+                    if choice.is_random() {
+                        // harlowe can pick one at random, but only as a macro,
+                        // so twine won't draw it on the story map
+                        let gotos: Vec<String> = export::evenly(choice)
+                            .into_iter()
+                            .map(|goto| format!("\"{}\"", goto))
+                            .collect();
+                        writeln!(
+                            twee,
+                            "(link-goto: \"{}\", (either: {}))",
+                            label.replace('\\', "\\\\").replace('"', "\\\""),
+                            gotos.join(", ")
+                        )
+                        .unwrap();
+                    } else {
+                        writeln!(twee, "[[{}->{}]]", label, choice.goto()).unwrap();
+                    }
+                }
+            }
+            writeln!(twee).unwrap();
+        }
+
+        (twee, warnings)
+    }
+
+    /// reads a story from twee 3. passage names are turned into section
+    /// identifiers, and it's then checked like [`Story::parse`], with errors on
+    /// the lines of the twee
+    pub fn from_twee(twee: &str) -> ParseResult<(Story, Vec<Warning>)> {
+        let mut warnings = Vec::new();
+        let mut title = None;
+        let mut start = None;
+        let mut sections = Vec::new();
+
+        // where the start is set, for errors about it
+        let mut start_line = 0;
+
+        for passage in passages(twee) {
+            let warning = |message| Warning::new(Some(passage.line_num), message);
+
+            if let Some(tag) = passage
+                .tags
+                .iter()
+                .find(|tag| matches!(**tag, "script" | "stylesheet"))
+            {
+                warnings.push(warning(format!(
+                    "{} is a {}, which isn't supported, so it's left out",
+                    passage.name, tag
+                )));
+                continue;
+            }
+
+            match passage.name.as_str() {
+                "StoryTitle" => title = Some(passage.text().trim().to_owned()),
+                "StoryData" => match serde_json::from_str::<Value>(&passage.text()) {
+                    Ok(data) => {
+                        if let Some(name) = data.get("start").and_then(Value::as_str) {
+                            start_line = passage.line_num;
+                            start = Some(SectionIdentifier::parse(
+                                (passage.line_num, &identifier(name)),
+                                false,
+                            )?);
+                        }
+                    }
+                    Err(err) => warnings.push(warning(format!(
+                        "StoryData isn't valid json, so it's left out: {}",
+                        err
+                    ))),
+                },
+                _ => {
+                    if !passage.tags.is_empty() {
+                        warnings.push(warning(format!(
+                            "the tags on {} are left out",
+                            passage.name
+                        )));
+                    }
+                    sections.push(passage.section(&mut warnings)?);
+                }
+            }
+        }
+
+        let metadata = Metadata::new(title, None, None, None, None, start, start_line)?;
+        Ok((Story::from_parts(metadata, sections)?, warnings))
+    }
+}
+
+struct Passage<'a> {
+    name: String,
+    tags: Vec<&'a str>,
+    line_num: usize,
+    header: &'a str,
+    lines: Vec<(usize, &'a str)>,
+}
+
+impl Passage<'_> {
+    fn text(&self) -> String {
+        let lines: Vec<&str> = self.lines.iter().map(|(_, line)| *line).collect();
+        lines.join("\n")
+    }
+
+    // the passage as a section, with its links as choices. a line that's only
+    // links goes away, and links in the middle of text leave their label
+    // behind
+    fn section(&self, warnings: &mut Vec<Warning>) -> ParseResult<Section> {
+        let mut warn = |line_num, message| warnings.push(Warning::new(Some(line_num), message));
+
+        let converted = identifier(&self.name);
+        if converted != self.name {
+            warn(
+                self.line_num,
+                format!("passage \"{}\" is called {}", self.name, converted),
+            );
+        }
+        let identifier = SectionIdentifier::parse((self.line_num, &converted), false)?;
+
+        // with the line each is on, for errors
+        let mut description: Vec<(usize, String)> = Vec::new();
+        let mut choices = Vec::new();
+        let mut macros = false;
+
+        for &(line_num, line) in &self.lines {
+            // what `[first]` and `[again]` paragraphs are exported as
+            let shown = shown_paragraph(line);
+            let line = shown.as_deref().unwrap_or(line);
+
+            // and what random choices are
+            if let Some((label, gotos)) = random_link(line) {
+                let outcomes = Outcome::parse_all((line_num, &random_outcomes(&gotos)))?;
+                choices.push(Choice::random(&label, outcomes).on_line(line_num));
+                continue;
+            }
+
+            if !macros && has_macros(line) {
+                warn(
+                    line_num,
+                    format!("{} uses macros, which are kept as plain text", self.name),
+                );
+                macros = true;
+            }
+
+            // the line with the links' labels left in, and without them
+            let mut text = String::new();
+            let mut unlinked = String::new();
+            let mut linked = false;
+            let mut rest = line;
+            while let Some((before, after)) = rest.split_once("[[")
+                && let Some((link, after)) = after.split_once("]]")
+            {
+                let (label, target) = link_parts(link);
+                let goto = SectionIdentifier::parse((line_num, &self::identifier(target)), false)?;
+                choices.push(Choice::new(label, goto).on_line(line_num));
+
+                text.push_str(before);
+                text.push_str(label);
+                unlinked.push_str(before);
+                linked = true;
+                rest = after;
+            }
+            text.push_str(rest);
+            unlinked.push_str(rest);
+
+            if linked {
+                if unlinked.trim().is_empty() {
+                    continue;
+                }
+                warn(
+                    line_num,
+                    format!(
+                        "the links in the middle of {} become choices after it",
+                        self.name
+                    ),
+                );
+            }
+            description.push((line_num, text));
+        }
+
+        // a passage with nowhere to go is the end of the story
+        if choices.is_empty() {
+            choices.push(Choice::new(
+                "",
+                SectionIdentifier::parse((0, "END"), false)?,
+            ));
+        }
+
+        let description: Vec<(usize, &str)> = description
+            .iter()
+            .map(|(line_num, text)| (*line_num, text.as_str()))
+            .collect();
+        Section::new(
+            identifier,
+            &description,
+            choices,
+            self.line_num,
+            self.header,
+        )
+    }
+}
+
+// `(if: visits is 1)[...]` as `[first] ...`, and `(if: visits > 1)[...]` as
+// `[again] ...`, when it's the whole line
+fn shown_paragraph(line: &str) -> Option<String> {
+    let line = line.trim();
+    let (marker, rest) = if let Some(rest) = line.strip_prefix("(if: visits is 1)[") {
+        ("[first]", rest)
+    } else {
+        ("[again]", line.strip_prefix("(if: visits > 1)[")?)
+    };
+    let text = rest.strip_suffix(']')?;
+
+    (!text.contains(['[', ']'])).then(|| format!("{} {}", marker, text))
+}
+
+// `(link-goto: "label", (either: "A", "B", "B"))`, when it's the whole line,
+// as the label and every passage it could go to
+fn random_link(line: &str) -> Option<(String, Vec<String>)> {
+    let rest = line.trim().strip_prefix("(link-goto:")?;
+    let (label, rest) = quoted(rest)?;
+    let mut rest = rest
+        .trim_start()
+        .strip_prefix(',')?
+        .trim_start()
+        .strip_prefix("(either:")?;
+
+    let mut gotos = Vec::new();
+    loop {
+        let (goto, after) = quoted(rest)?;
+        gotos.push(goto);
+        match after.trim_start().strip_prefix(',') {
+            Some(after) => rest = after,
+            None => {
+                rest = after;
+                break;
+            }
+        }
+    }
+
+    let rest = rest.trim_start().strip_prefix(')')?;
+    let rest = rest.trim_start().strip_prefix(')')?;
+    rest.trim().is_empty().then_some((label, gotos))
+}
+
+// a harlowe string at the start, unescaped, and what's after it
+fn quoted(str: &str) -> Option<(String, &str)> {
+    let str = str.trim_start().strip_prefix('"')?;
+    let mut text = String::new();
+    let mut chars = str.char_indices();
+
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '\\' => text.push(chars.next()?.1),
+            '"' => return Some((text, &str[i + 1..])),
+            _ => text.push(ch),
+        }
+    }
+    None
+}
+
+// each passage picked from as often as it's listed, as `A 25% | B 75%`, like
+// the export writes them
+fn random_outcomes(gotos: &[String]) -> String {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for goto in gotos {
+        let goto = identifier(goto);
+        match counts.iter_mut().find(|(other, _)| *other == goto) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((goto, 1)),
+        }
+    }
+
+    let outcomes: Vec<String> = counts
+        .iter()
+        .map(|(goto, count)| format!("{} {}%", goto, count * 100 / gotos.len()))
+        .collect();
+    outcomes.join(" | ")
+}
+
+// splits the passages out of a twee file, ignoring anything before the first
+fn passages(twee: &str) -> Vec<Passage<'_>> {
+    let mut passages: Vec<Passage> = Vec::new();
+
+    for (line_num, line) in twee.lines().enumerate() {
+        if let Some(header) = line.strip_prefix("::") {
+            let (name, tags) = passage_header(header);
+            passages.push(Passage {
+                name,
+                tags,
+                line_num,
+                header: line,
+                lines: Vec::new(),
+            });
+        } else if let Some(passage) = passages.last_mut() {
+            passage.lines.push((line_num, line));
+        }
+    }
+
+    // the blank lines between passages aren't part of them
+    for passage in &mut passages {
+        while passage
+            .lines
+            .last()
+            .is_some_and(|(_, line)| line.trim().is_empty())
+        {
+            passage.lines.pop();
+        }
+    }
+
+    passages
+}
+
+// `Name [tags] {"position":"100,100"}`, where the name can escape `[`, `{`
+// and `\` with a backslash
+fn passage_header(header: &str) -> (String, Vec<&str>) {
+    let mut name = String::new();
+    let mut chars = header.trim().char_indices();
+    let mut rest = "";
+
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '\\' => {
+                if let Some((_, ch)) = chars.next() {
+                    name.push(ch);
+                }
+            }
+            '[' | '{' => {
+                rest = &header.trim()[i..];
+                break;
+            }
+            _ => name.push(ch),
+        }
+    }
+
+    let tags = rest
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once(']'))
+        .map(|(tags, _)| tags.split_whitespace().collect())
+        .unwrap_or_default();
+
+    (name.trim().to_owned(), tags)
+}
+
+// the label and target of a link, in any of the forms twine understands:
+// `[[label->Target]]`, `[[Target<-label]]`, `[[label|Target]]` and
+// `[[Target]]`, with any `][setter]` dropped
+fn link_parts(link: &str) -> (&str, &str) {
+    let link = link.split_once("][").map_or(link, |(link, _)| link);
+
+    let (label, target) = if let Some((label, target)) = link.rsplit_once("->") {
+        (label, target)
+    } else if let Some((target, label)) = link.split_once("<-") {
+        (label, target)
+    } else if let Some((label, target)) = link.split_once('|') {
+        (label, target)
+    } else {
+        (link, link)
+    };
+
+    let (label, target) = (label.trim(), target.trim());
+    // there has to be something to click on
+    (if label.is_empty() { target } else { label }, target)
+}
+
+// harlowe's `(set: ...)` and sugarcube's `<<set ...>>`
+fn has_macros(line: &str) -> bool {
+    line.contains("<<")
+        || line.match_indices('(').any(|(i, _)| {
+            let name: String = line[i + 1..]
+                .chars()
+                .take_while(|ch| ch.is_ascii_alphanumeric() || *ch == '-')
+                .collect();
+            !name.is_empty() && line[i + 1 + name.len()..].starts_with(':')
+        })
+}
+
+// passage names are free text, so they're made to fit: uppercase, with
+// anything that can't be in an identifier as an underscore. `::` is kept, for
+// chapters
+fn identifier(name: &str) -> String {
+    let parts: Vec<String> = name
+        .split("::")
+        .map(|part| {
+            part.trim()
+                .chars()
+                .map(|ch| match ch {
+                    'A'..='Z' | '0'..='9' | '_' => ch,
+                    'a'..='z' => ch.to_ascii_uppercase(),
+                    _ => '_',
+                })
+                .collect()
+        })
+        .collect();
+    parts.join("::")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ParseErrorType;
+
+    const STORY: &str = "\
+---
+title: The Lighthouse
+author: Someone
+start: CH1::SHORE
+---
+HOME:
+You go home.
+-> END
+---
+chapter CH1
+SHORE:
+The lamp's out.
+
+Someone has to climb up.
+climb -> STAIRS
+leave -> HOME
+---
+STAIRS:
+It's a long way.
+-> LAMP
+---
+LAMP:
+You light it.
+-> END
+---";
+
+    #[test]
+    fn to_twee() {
+        let story = Story::parse(STORY).unwrap();
+        let (twee, warnings) = story.to_twee();
+
+        assert!(twee.starts_with(":: StoryTitle\nThe Lighthouse\n\n:: StoryData\n{\n"));
+        assert!(twee.contains("\"start\": \"CH1::SHORE\""));
+        // chapters are in the passage names, and the shorthands are links
+        assert!(twee.ends_with(
+            "\
+:: HOME
+You go home.
+
+:: CH1::SHORE
+The lamp's out.
+
+Someone has to climb up.
+
+[[climb->CH1::STAIRS]]
+[[leave->HOME]]
+
+:: CH1::STAIRS
+It's a long way.
+
+[[Continue...->CH1::LAMP]]
+
+:: CH1::LAMP
+You light it.
+
+"
+        ));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message().contains("author"));
+
+        // and back again, without the author
+        let (read, warnings) = Story::from_twee(&twee).unwrap();
+        assert!(warnings.is_empty());
+        let without_author = Story::parse(&STORY.replace("author: Someone\n", "")).unwrap();
+        assert_eq!(read.without_locations(), without_author.without_locations());
+        assert_eq!(story.to_twee().0, twee);
+    }
+
+    #[test]
+    fn random() {
+        let story = Story::parse(
+            "\
+START:
+Heads or tails?
+say \"heads\" -> HEADS 25% | TAILS 75%
+---
+HEADS:
+Heads.
+-> END
+---
+TAILS:
+Tails.
+-> END
+---",
+        )
+        .unwrap();
+
+        // left to harlowe
+        let (twee, _) = story.to_twee();
+        assert!(twee.contains(
+            "(link-goto: \"say \\\"heads\\\"\", (either: \"HEADS\", \"TAILS\", \"TAILS\", \"TAILS\"))\n"
+        ));
+
+        // and read back as the same choice
+        let (read, warnings) = Story::from_twee(&twee).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(read.without_locations(), story.without_locations());
+    }
+
+    #[test]
+    fn visits() {
+        let story = Story::parse(
+            "\
+START:
+[first] You see a door.
+
+[again] It's still shut.
+* open it -> ROOM
+wait -> START
+---
+ROOM:
+It's empty.
+-> END
+---",
+        )
+        .unwrap();
+
+        // left to harlowe too, apart from once-only choices
+        let (twee, warnings) = story.to_twee();
+        assert!(twee.contains(
+            "(if: visits is 1)[You see a door.]\n\n(if: visits > 1)[It's still shut.]\n"
+        ));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message().contains("once-only"));
+
+        let (read, warnings) = Story::from_twee(&twee).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(
+            read.sections()[0].description(),
+            story.sections()[0].description()
+        );
+    }
+
+    #[test]
+    fn from_twee() {
+        let twee = "\
+:: StoryTitle
+Lost
+
+:: StoryData
+{ \"ifid\": \"x\", \"start\": \"Start Here\" }
+
+:: Start Here [dark] {\"position\":\"100,100\"}
+You're in a (set: $lost to true) cave.
+Go [[left->Left]] or [[right|Right]]?
+[[Wait]]
+
+:: Left
+A wall.
+[[Start Here<-Back]]
+
+:: Right
+Daylight!
+
+:: Wait
+You wait.
+[[Start Here]]
+
+:: Style [stylesheet]
+body { color: red; }
+";
+        let (story, warnings) = Story::from_twee(twee).unwrap();
+
+        assert_eq!(story.metadata().title(), Some("Lost"));
+        assert_eq!(story.metadata().start().to_string(), "START_HERE");
+
+        let start = story.sections().values().next().unwrap();
+        assert_eq!(start.identifier().to_string(), "START_HERE");
+        assert_eq!(
+            start.description().to_string(),
+            "You're in a (set: $lost to true) cave. Go left or right?"
+        );
+        let choices: Vec<(String, String)> = start
+            .choices()
+            .iter()
+            .map(|choice| (choice.label(), choice.goto().to_string()))
+            .collect();
+        assert_eq!(
+            choices,
+            [("left", "LEFT"), ("right", "RIGHT"), ("Wait", "WAIT")]
+                .map(|(label, goto)| (label.to_owned(), goto.to_owned()))
+        );
+        assert_eq!(
+            story.sections().values().nth(1).unwrap().choices()[0]
+                .goto()
+                .to_string(),
+            "START_HERE"
+        );
+        assert!(story.sections().values().nth(2).unwrap().is_ending());
+        assert_eq!(story.sections().len(), 4);
+
+        let warnings: Vec<(Option<usize>, bool)> = warnings
+            .iter()
+            .map(|warning| (warning.line_num(), warning.message().contains("left out")))
+            .collect();
+        assert!(warnings.contains(&(Some(6), true)), "the tags");
+        assert!(warnings.contains(&(Some(6), false)), "the renaming");
+        assert!(warnings.contains(&(Some(7), false)), "the macros");
+        assert!(warnings.contains(&(Some(8), false)), "the inline links");
+        assert!(warnings.contains(&(Some(22), true)), "the stylesheet");
+    }
+
+    #[test]
+    fn errors() {
+        let error = |twee: &str| {
+            let err = Story::from_twee(twee).unwrap_err();
+            (err.error_type().to_string(), err.line_num())
+        };
+
+        assert_eq!(
+            error(":: START\nhello\n\n[[Nowhere]]\n"),
+            (ParseErrorType::DanglingGoto.to_string(), 3)
+        );
+        let duplicate = Story::from_twee(":: START\nhello\n\n:: Start\nagain\n").unwrap_err();
+        assert!(matches!(
+            duplicate.error_type(),
+            ParseErrorType::DuplicateSections(..)
+        ));
+        assert_eq!(duplicate.line_num(), 3);
+        // on the line it's on, rather than the passage's
+        assert_eq!(
+            error(":: Start\nhello\n\n---\n\nbye\n[[Start]]\n"),
+            (ParseErrorType::UnexpectedSeparator.to_string(), 3)
+        );
+        assert_eq!(
+            error(":: START\n[[START]]\n"),
+            (ParseErrorType::ExpectedDescription.to_string(), 0)
+        );
+        assert_eq!(
+            error(":: StoryData\n{\"start\": \"End\"}\n\n:: START\nhi\n"),
+            (ParseErrorType::ReservedKeyUsage.to_string(), 0)
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use std::{collections::HashSet, fmt::Write};
+
+use crate::parse::{Section, SectionIdentifier, Story};
+
+// how many sections the search can go through for each playthrough it's
+// allowed to find, so a tangle of loops with no way out still stops
+const STATES_PER_PLAYTHROUGH: usize = 1_000;
+
+/// a way of getting from the start section to an ending, without visiting any
+/// section twice
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Playthrough {
+    steps: Vec<Step>,
+    ending: SectionIdentifier,
+}
+
+/// being at a section, and taking one of its choices
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    section: SectionIdentifier,
+    /// the index of the choice in the section's choices
+    choice: usize,
+}
+
+/// every playthrough of a story, up to a limit
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Playthroughs {
+    playthroughs: Vec<Playthrough>,
+    /// whether we stopped at the limit, so there could be more we didn't find
+    truncated: bool,
+}
+
+impl Story {
+    /// every playthrough from the start section to an ending, in the order of
+    /// the choices, stopping after `limit` of them, or after searching for
+    /// long enough to have found that many. going back to a section that's
+    /// already been visited is skipped, since it'd loop forever
+    pub fn playthroughs(&self, limit: usize) -> Playthroughs {
+        struct Walk<'a> {
+            story: &'a Story,
+            limit: usize,
+            /// how many more sections the search can go through
+            budget: usize,
+            steps: Vec<Step>,
+            visited: HashSet<&'a SectionIdentifier>,
+            found: Playthroughs,
+        }
+
+        fn walk<'a>(walk_state: &mut Walk<'a>, identifier: &'a SectionIdentifier) {
+            if walk_state.found.truncated {
+                return;
+            }
+            let Some(budget) = walk_state.budget.checked_sub(1) else {
+                walk_state.found.truncated = true;
+                return;
+            };
+            walk_state.budget = budget;
+
+            let section = &walk_state.story.sections()[identifier];
+            if section.is_ending() {
+                if walk_state.found.playthroughs.len() >= walk_state.limit {
+                    walk_state.found.truncated = true;
+                } else {
+                    walk_state.found.playthroughs.push(Playthrough {
+                        steps: walk_state.steps.clone(),
+                        ending: identifier.clone(),
+                    });
+                }
+                return;
+            }
+
+            walk_state.visited.insert(identifier);
+            for (i, choice) in section.choices().iter().enumerate() {
+                // a random choice could go any of its ways, so they all count
+                for goto in choice.gotos() {
+                    if walk_state.visited.contains(goto) {
+                        continue;
+                    }
+
+                    walk_state.steps.push(Step {
+                        section: identifier.clone(),
+                        choice: i,
+                    });
+                    walk(walk_state, goto);
+                    walk_state.steps.pop();
+                }
+            }
+            walk_state.visited.remove(identifier);
+        }
+
+        let mut walk_state = Walk {
+            story: self,
+            limit,
+            budget: limit.saturating_mul(STATES_PER_PLAYTHROUGH),
+            steps: Vec::new(),
+            visited: HashSet::new(),
+            found: Playthroughs {
+                playthroughs: Vec::new(),
+                truncated: false,
+            },
+        };
+        walk(&mut walk_state, self.metadata().start());
+
+        walk_state.found
+    }
+}
+
+impl Playthrough {
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn ending(&self) -> &SectionIdentifier {
+        &self.ending
+    }
+}
+
+impl Step {
+    pub fn section(&self) -> &SectionIdentifier {
+        &self.section
+    }
+
+    pub fn choice(&self) -> usize {
+        self.choice
+    }
+}
+
+impl Playthroughs {
+    pub fn playthroughs(&self) -> &[Playthrough] {
+        &self.playthroughs
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+/// the playthroughs as numbered walkthroughs, with the text of every section
+/// and the choice taken at each
+pub fn walkthroughs(story: &Story, playthroughs: &Playthroughs) -> String {
+    let mut text = String::new();
+
+    for (i, playthrough) in playthroughs.playthroughs.iter().enumerate() {
+        if i > 0 {
+            writeln!(text).unwrap();
+        }
+
+        let route: Vec<String> = playthrough
+            .steps
+            .iter()
+            .map(|step| step.section.to_string())
+            .chain([playthrough.ending.to_string()])
+            .collect();
+        writeln!(text, "Walkthrough {}: {}", i + 1, route.join(" -> ")).unwrap();
+
+        for step in &playthrough.steps {
+            let section = &story.sections()[&step.section];
+            writeln!(text, "\n{}", section.identifier()).unwrap();
+            write_description(&mut text, section);
+            writeln!(text, "    > {}", section.choices()[step.choice].label()).unwrap();
+        }
+
+        let ending = &story.sections()[&playthrough.ending];
+        writeln!(text, "\n{}", ending.identifier()).unwrap();
+        write_description(&mut text, ending);
+        writeln!(text, "    THE END").unwrap();
+    }
+
+    if playthroughs.truncated {
+        writeln!(
+            text,
+            "\n(stopped after {} walkthroughs, there could be more)",
+            playthroughs.playthroughs.len()
+        )
+        .unwrap();
+    }
+
+    text
+}
+
+// a playthrough never goes back to a section, so it's always what's shown on
+// the first visit
+fn write_description(text: &mut String, section: &Section) {
+    let paragraphs = section
+        .description()
+        .paragraphs()
+        .iter()
+        .filter(|paragraph| paragraph.is_shown(1));
+
+    for (i, paragraph) in paragraphs.enumerate() {
+        if i > 0 {
+            writeln!(text).unwrap();
+        }
+        writeln!(text, "    {}", paragraph.text()).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STORY: &str = "\
+START:
+Believe you can fly?
+yes -> FLY
+no -> CLIFF
+---
+CLIFF:
+You arrive at the cliffside.
+
+[again] The cliffside again.
+jump -> FLY
+go home -> START
+---
+FLY:
+You can't.
+-> END
+---";
+
+    #[test]
+    fn playthroughs() {
+        let story = Story::parse(STORY).unwrap();
+
+        let playthroughs = story.playthroughs(100);
+        assert!(!playthroughs.truncated());
+
+        let routes: Vec<Vec<(String, usize)>> = playthroughs
+            .playthroughs()
+            .iter()
+            .map(|playthrough| {
+                playthrough
+                    .steps()
+                    .iter()
+                    .map(|step| (step.section().to_string(), step.choice()))
+                    .collect()
+            })
+            .collect();
+        // going home from the cliff loops back to the start, so it's skipped
+        assert_eq!(
+            routes,
+            vec![
+                vec![("START".to_owned(), 0)],
+                vec![("START".to_owned(), 1), ("CLIFF".to_owned(), 0)],
+            ]
+        );
+
+        let limited = story.playthroughs(1);
+        assert_eq!(limited.playthroughs().len(), 1);
+        assert!(limited.truncated());
+    }
+
+    #[test]
+    fn no_ending() {
+        // every section goes to every other one, and none of them to an
+        // ending, so there's a lot of searching for nothing
+        let names: Vec<String> = (0..12).map(|i| format!("S{}", i)).collect();
+        let mut source = String::new();
+        for name in &names {
+            let header = if name == "S0" { "START" } else { name };
+            source.push_str(&format!("{}:\nlost\n", header));
+            for other in names
+                .iter()
+                .filter(|other| *other != name && *other != "S0")
+            {
+                source.push_str(&format!("to {} -> {}\n", other, other));
+            }
+            source.push_str("back -> START\n---\n");
+        }
+        let story = Story::parse(&source).unwrap();
+
+        let playthroughs = story.playthroughs(10);
+        assert!(playthroughs.playthroughs().is_empty());
+        assert!(playthroughs.truncated());
+    }
+
+    #[test]
+    fn random() {
+        let story = Story::parse(
+            "\
+START:
+Flip a coin.
+flip -> HEADS 50% | TAILS 50%
+---
+HEADS:
+Heads.
+-> END
+---
+TAILS:
+Tails.
+-> END
+---",
+        )
+        .unwrap();
+
+        // one for each way the coin could land
+        let endings: Vec<String> = story
+            .playthroughs(100)
+            .playthroughs()
+            .iter()
+            .map(|playthrough| playthrough.ending().to_string())
+            .collect();
+        assert_eq!(endings, ["HEADS", "TAILS"]);
+    }
+
+    #[test]
+    fn walkthroughs() {
+        let story = Story::parse(STORY).unwrap();
+
+        assert_eq!(
+            super::walkthroughs(&story, &story.playthroughs(1)),
+            "\
+Walkthrough 1: START -> FLY
+
+START
+    Believe you can fly?
+    > yes
+
+FLY
+    You can't.
+    THE END
+
+(stopped after 1 walkthroughs, there could be more)
+"
+        );
+    }
+}
@@ -1,13 +1,39 @@
-use crate::parse::{self, Section, SectionIdentifier, Story};
+use crate::{
+    parse::{self, MemoryLoader, Metadata, Section, Story},
+    runtime::{Position, Session},
+};
 use js_sys::{JsString, Reflect};
-use std::{cell::RefCell, rc::Rc, sync::Mutex};
+use std::{
+    cell::RefCell,
+    path::PathBuf,
+    rc::Rc,
+    sync::{Mutex, OnceLock},
+};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{Element, Event, PointerEvent, Request, RequestInit, RequestMode, Response, console};
 
+// the story's kept for as long as the page is open, so the session can
+// borrow it for good
+static STORY: OnceLock<Story> = OnceLock::new();
+
 thread_local! {
     // static STORY: LazyCell<Mutex<Option<Story>>> = LazyCell::new(|| Mutex::new(None));
-    static STORY: RefCell<Story> = unreachable!("initialized before use");
+    static SESSION: RefCell<Session<'static>> = unreachable!("initialized before use");
+}
+
+// random choices go differently every time, unless the page is opened with
+// `?seed=N`, so a playthrough can be repeated
+fn seed() -> u64 {
+    web_sys::window()
+        .and_then(|window| window.location().search().ok())
+        .and_then(|search| {
+            search
+                .trim_start_matches('?')
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("seed=")?.parse().ok())
+        })
+        .unwrap_or_else(|| js_sys::Date::now() as u64)
 }
 
 // TODO:
@@ -22,40 +48,34 @@ pub fn run() {
         // p.set_text_content(Some("Hello World!!!"));
         // body.append_child(&p).unwrap();
 
-        let req = RequestInit::new();
-        req.set_method("GET");
-        req.set_mode(RequestMode::SameOrigin);
-
         let page = "iraq-2004.fater";
 
-        let req = Request::new_with_str_and_init(&page, &req).unwrap();
-
-        let resp = JsFuture::from(window.fetch_with_request(&req))
-            .await
-            .unwrap();
-
-        let resp: Response = resp.dyn_into().unwrap();
-
-        let text_js = JsFuture::from(resp.text().unwrap()).await.unwrap();
-        // let js_string = JsString::from(text_js);
-        // let string = String::from(js_string);
-        let string = text_js.as_string().unwrap();
+        // we can't read includes from disk, so fetch every file the story
+        // pulls in before parsing, and hand them to the parser from memory
+        let mut loader = MemoryLoader::default();
+        let mut pending = vec![PathBuf::from(page)];
+        while let Some(path) = pending.pop() {
+            if loader.contains(&path) {
+                continue;
+            }
+
+            let string = fetch(&window, &path.to_string_lossy()).await;
+            pending.extend(parse::includes(&string, &path));
+            loader.insert(path, string);
+        }
 
-        let story = parse::load_str(&string).unwrap();
+        let story = parse::load_file_with(page, &mut loader).unwrap().unwrap();
+        let story = STORY.get_or_init(|| story);
 
         // console::log_1(&string.clone().into());
         console::log_1(&format!("{:?}", story).into());
 
+        document.set_title(story.metadata().title().unwrap_or("Fater"));
+
         document
             .get_element_by_id("layout")
             .unwrap()
-            .set_inner_html(
-                &story
-                    .sections()
-                    .get(&SectionIdentifier::parse((0, "START"), false).unwrap())
-                    .unwrap()
-                    .to_string(),
-            );
+            .set_inner_html(&menu_html(story.metadata()));
 
         // let p = document.create_element("p").expect("p is valid element");
         // p.set_text_content(Some(&string));
@@ -71,7 +91,7 @@ pub fn run() {
         // STORY.with(move |st| {
         //     *st.borrow_mut() = Some(story);
         // });
-        STORY.set(story);
+        SESSION.set(Session::with_seed(story, seed()));
 
         // let container = Rc::new(RefCell::new(None));
 
@@ -106,6 +126,25 @@ pub fn run() {
     });
 }
 
+async fn fetch(window: &web_sys::Window, page: &str) -> String {
+    let req = RequestInit::new();
+    req.set_method("GET");
+    req.set_mode(RequestMode::SameOrigin);
+
+    let req = Request::new_with_str_and_init(page, &req).unwrap();
+
+    let resp = JsFuture::from(window.fetch_with_request(&req))
+        .await
+        .unwrap();
+
+    let resp: Response = resp.dyn_into().unwrap();
+
+    let text_js = JsFuture::from(resp.text().unwrap()).await.unwrap();
+    // let js_string = JsString::from(text_js);
+    // let string = String::from(js_string);
+    text_js.as_string().unwrap()
+}
+
 // async fn handle_click() {
 //     let window = web_sys::window().expect("no global `window` exists");
 //     let document = window.document().expect("should have a document on window");
@@ -124,13 +163,12 @@ fn handle_click(event: PointerEvent) {
     // p.set_text_content(Some("Hello World!!!"));
     // body.append_child(&p).unwrap();
 
-    let element = event.target().unwrap().dyn_into::<Element>().unwrap();
-    // console::log_1(&element.get_attribute("data-fater-goto").unwrap().into());
-    let goto = SectionIdentifier::parse(
-        (0, &element.get_attribute("data-fater-goto").unwrap()),
-        false,
-    )
-    .unwrap();
+    // the choice itself, rather than whatever in it was clicked on
+    let element = event
+        .current_target()
+        .unwrap()
+        .dyn_into::<Element>()
+        .unwrap();
 
     // let str = Reflect::get(&window, &"story".into())
     //     .unwrap()
@@ -141,21 +179,24 @@ fn handle_click(event: PointerEvent) {
     //     .unwrap()
     //     .unchecked_into();
 
-    STORY.with_borrow_mut(|story| {
-        console::log_1(&format!("{:?}", story.sections().get(&goto).unwrap()).into());
-        console::log_1(
-            &story
-                .sections()
-                .get(&SectionIdentifier::parse((0, "START"), false).unwrap())
-                .unwrap()
-                .to_string()
-                .into(),
-        );
-        document
-            .get_element_by_id("layout")
-            .unwrap()
-            .set_inner_html(&story.sections().get(&goto).unwrap().to_string());
+    let html = SESSION.with_borrow_mut(|session| {
+        // the menu's "Begin" is the only one without an index
+        match element.get_attribute("data-fater-choice") {
+            Some(index) => {
+                session.choose(index.parse().unwrap()).unwrap();
+            }
+            None => session.begin(),
+        }
+
+        match session.position() {
+            Position::Menu => menu_html(session.story().metadata()),
+            Position::Section(_) => section_html(session),
+        }
     });
+    document
+        .get_element_by_id("layout")
+        .unwrap()
+        .set_inner_html(&html);
     let choices = document.query_selector_all(".choice").unwrap();
 
     let closure = Closure::wrap(Box::new(handle_click) as Box<dyn FnMut(_)>);
@@ -170,3 +211,62 @@ fn handle_click(event: PointerEvent) {
 
     closure.forget();
 }
+
+// the section the session's at, with only the paragraphs shown on this visit,
+// and the choices that haven't been used up
+fn section_html(session: &Session) -> String {
+    let paragraphs: Vec<&str> = session
+        .paragraphs()
+        .iter()
+        .map(|paragraph| paragraph.text())
+        .collect();
+    let mut html = format!(
+        r#"<div id="description">
+    <p>
+        {}
+    </p>
+</div>
+<div id="choices">"#,
+        paragraphs.join("\n\n")
+    );
+
+    for (i, choice) in session.choices().iter().enumerate() {
+        html.push_str(&format!(
+            r#"<div class="choice" data-fater-choice="{}">
+    <span>
+        {}
+    </span>
+</div>"#,
+            i,
+            choice.label()
+        ));
+    }
+
+    html.push_str("</div>");
+    html
+}
+
+// the title screen, shown on load and when a story ends with "Return to menu"
+fn menu_html(metadata: &Metadata) -> String {
+    let mut description = metadata.title().unwrap_or("Fater").to_owned();
+    if let Some(author) = metadata.author() {
+        description.push_str(&format!("<br>by {}", author));
+    }
+    if let Some(desc) = metadata.description() {
+        description.push_str(&format!("<br><br>{}", desc));
+    }
+
+    format!(
+        r#"<div id="description">
+    <p>
+        {}
+    </p>
+</div>
+<div id="choices"><div class="choice">
+    <span>
+        Begin
+    </span>
+</div></div>"#,
+        description
+    )
+}